@@ -1,4 +1,4 @@
-use crate::model::{Brand, DatabaseState};
+use crate::model::{Brand, BrandAlias, DatabaseState, SpiderBrand};
 use actix_web::{
     HttpResponse, Responder, get,
     web::{Data, Path},
@@ -36,7 +36,7 @@ async fn get_random_brands(state: Data<DatabaseState>, path: Path<i64>) -> impl
         return HttpResponse::BadRequest().body(format!("Limit must be less than {}", max_limit));
     }
 
-    match sqlx::query_as::<_, Brand>("SELECT * FROM brand LIMIT $1")
+    match sqlx::query_as::<_, Brand>("SELECT * FROM brand WHERE merged_into_id IS NULL LIMIT $1")
         .bind(limit)
         .fetch_all(&state.brand_db)
         .await
@@ -51,3 +51,61 @@ async fn get_random_brands(state: Data<DatabaseState>, path: Path<i64>) -> impl
         }
     }
 }
+
+#[get("/brand/{id}/spiders")]
+async fn get_brand_spiders(state: Data<DatabaseState>, path: Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    debug!("Getting the spiders of the brand with id: {}", id);
+    match sqlx::query_as::<_, SpiderBrand>(
+        "SELECT * FROM spider_brand WHERE brand_id = $1 ORDER BY poi_count DESC",
+    )
+    .bind(id)
+    .fetch_all(&state.poi_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while getting the spiders of brand {}: {}", id, why);
+            HttpResponse::InternalServerError().body(format!(
+                "Error while getting the spiders of brand {}: {}",
+                id, why
+            ))
+        }
+        Ok(spiders) => {
+            info!(
+                "Found {} spiders for the brand with id: {}",
+                spiders.len(),
+                id
+            );
+            HttpResponse::Ok().json(spiders)
+        }
+    }
+}
+
+#[get("/brand/{id}/aliases")]
+async fn get_brand_aliases(state: Data<DatabaseState>, path: Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    debug!("Getting the aliases of the brand with id: {}", id);
+    match sqlx::query_as::<_, BrandAlias>(
+        "SELECT * FROM brand_alias WHERE brand_id = $1 ORDER BY name",
+    )
+    .bind(id)
+    .fetch_all(&state.brand_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while getting the aliases of brand {}: {}", id, why);
+            HttpResponse::InternalServerError().body(format!(
+                "Error while getting the aliases of brand {}: {}",
+                id, why
+            ))
+        }
+        Ok(aliases) => {
+            info!(
+                "Found {} aliases for the brand with id: {}",
+                aliases.len(),
+                id
+            );
+            HttpResponse::Ok().json(aliases)
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use crate::model::{Category, DatabaseState};
+use actix_web::{HttpResponse, Responder, get, web::Data};
+use log::{debug, error, info};
+
+#[get("/category")]
+async fn get_categories(state: Data<DatabaseState>) -> impl Responder {
+    debug!("Request received to list the categories");
+    match sqlx::query_as::<_, Category>(
+        "SELECT category.id, category.key, category.value, COUNT(poi.id) AS poi_count
+        FROM category
+        LEFT JOIN poi ON poi.category_id = category.id AND poi.deleted_at IS NULL
+        GROUP BY category.id
+        ORDER BY category.key, category.value",
+    )
+    .fetch_all(&state.poi_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while listing the categories: {}", why);
+            HttpResponse::InternalServerError()
+                .body(format!("Error while listing the categories: {}", why))
+        }
+        Ok(categories) => {
+            info!("Successfully retrieved {} categories", categories.len());
+            HttpResponse::Ok().json(categories)
+        }
+    }
+}
@@ -1,6 +1,10 @@
 mod brand;
+mod category;
 mod model;
+mod operator;
 mod poi;
+mod region;
+mod spider;
 
 use actix_web::{App, HttpServer, web};
 use log::{debug, info};
@@ -26,11 +30,22 @@ async fn main() -> Result<(), std::io::Error> {
     HttpServer::new(move || {
         App::new()
             .app_data(app_data.clone())
+            .service(poi::search_pois)
             .service(poi::get_poi_by_id)
             .service(poi::get_random_pois)
             .service(poi::get_poi_count_for_brand_id)
             .service(brand::get_brand_by_id)
             .service(brand::get_random_brands)
+            .service(brand::get_brand_spiders)
+            .service(brand::get_brand_aliases)
+            .service(operator::get_operator_by_id)
+            .service(operator::get_operator_brands)
+            .service(operator::get_operator_pois)
+            .service(spider::get_spiders)
+            .service(spider::get_spider_by_id)
+            .service(spider::get_spider_pois)
+            .service(category::get_categories)
+            .service(region::get_regions)
     })
     .bind(("0.0.0.0", 8080))?
     .run()
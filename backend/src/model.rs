@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use geozero::{ToWkt, wkb};
 use serde::{Serialize, ser::SerializeStruct};
 use sqlx::FromRow;
@@ -29,7 +30,76 @@ impl DatabaseState {
 pub struct Brand {
     pub id: i32,
     pub name: String,
+    pub normalized_name: String,
     pub wikidata_id: Option<String>,
+    /// The brand this one turned out to be, its POIs now point there.
+    pub merged_into_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct BrandAlias {
+    pub brand_id: i32,
+    pub name: String,
+    pub normalized_name: String,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct Operator {
+    pub id: i32,
+    pub name: String,
+    pub wikidata_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct BrandOperator {
+    pub brand_id: i32,
+    pub operator_id: i32,
+    pub poi_count: i32,
+}
+
+/// A brand run by an operator, with the number of its POIs the operator runs.
+#[derive(Serialize, Debug)]
+pub struct OperatorBrand {
+    #[serde(flatten)]
+    pub brand: Brand,
+    pub poi_count: i32,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct Spider {
+    pub id: String,
+    pub source_file: String,
+    pub brand_ids: Vec<i32>,
+    pub feature_count: i32,
+    pub previous_feature_count: Option<i32>,
+    pub features_dropped: i32,
+    pub last_run_id: Option<i32>,
+    pub status: String,
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct SpiderBrand {
+    pub spider_id: String,
+    pub brand_id: i32,
+    pub poi_count: i32,
+}
+
+/// The number of POIs in an ISO 3166-2 subdivision.
+#[derive(Serialize, Debug, FromRow)]
+pub struct Region {
+    pub subdivision_code: String,
+    pub country_code: String,
+    pub poi_count: i64,
+}
+
+#[derive(Serialize, Debug, FromRow)]
+pub struct Category {
+    pub id: i32,
+    pub key: String,
+    pub value: String,
+    pub poi_count: i64,
 }
 
 #[derive(Debug, FromRow)]
@@ -37,20 +107,53 @@ pub struct Poi {
     pub id: i32,
     pub spider_id: String,
     pub poi_name: Option<String>,
-    pub brand_id: i32,
+    pub brand_id: Option<i32>,
     pub website: Option<String>,
+    pub website_host: Option<String>,
+    pub website_source: Option<String>,
     pub opening_hours: Option<String>,
+    pub opening_schedule: Option<serde_json::Value>,
+    pub opening_hours_error: Option<String>,
+    pub timezone: Option<String>,
     pub phone: Option<String>,
+    pub phones: Vec<String>,
+    pub invalid_phones: Vec<String>,
     pub point: wkb::Decode<geo_types::Geometry<f64>>,
     pub city: Option<String>,
     pub zipcode: Option<String>,
     pub house_number: Option<String>,
     pub street_address: Option<String>,
     pub country: Option<String>,
-    pub country_code: String,
+    pub country_code: Option<String>,
+    pub geocode_status: String,
+    pub coordinate_flags: Vec<String>,
+    pub subdivision_code: Option<String>,
+    pub admin_areas: Option<serde_json::Value>,
     pub state: Option<String>,
     pub full_address: Option<String>,
     pub street_name: Option<String>,
+    pub address_derived: Vec<String>,
+    pub postcode_valid: Option<bool>,
+    pub poi_ref: Option<String>,
+    pub branch: Option<String>,
+    pub email: Option<String>,
+    pub contact_twitter: Option<String>,
+    pub contact_facebook: Option<String>,
+    pub image: Option<String>,
+    pub located_in: Option<String>,
+    pub located_in_wikidata_id: Option<String>,
+    pub nsi_id: Option<String>,
+    pub end_date: Option<String>,
+    pub operator: Option<String>,
+    pub operator_wikidata_id: Option<String>,
+    pub operator_id: Option<i32>,
+    pub tags: serde_json::Value,
+    pub category_id: Option<i32>,
+    pub feature_id: String,
+    pub run_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl Serialize for Poi {
@@ -58,14 +161,21 @@ impl Serialize for Poi {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("POI", 18)?;
+        let mut state = serializer.serialize_struct("POI", 50)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("spider_id", &self.spider_id)?;
         state.serialize_field("poi_name", &self.poi_name)?;
         state.serialize_field("brand_id", &self.brand_id)?;
         state.serialize_field("website", &self.website)?;
+        state.serialize_field("website_host", &self.website_host)?;
+        state.serialize_field("website_source", &self.website_source)?;
         state.serialize_field("opening_hours", &self.opening_hours)?;
+        state.serialize_field("opening_schedule", &self.opening_schedule)?;
+        state.serialize_field("opening_hours_error", &self.opening_hours_error)?;
+        state.serialize_field("timezone", &self.timezone)?;
         state.serialize_field("phone", &self.phone)?;
+        state.serialize_field("phones", &self.phones)?;
+        state.serialize_field("invalid_phones", &self.invalid_phones)?;
         state.serialize_field(
             "point",
             &self
@@ -80,9 +190,35 @@ impl Serialize for Poi {
         state.serialize_field("street_address", &self.street_address)?;
         state.serialize_field("country", &self.country)?;
         state.serialize_field("country_code", &self.country_code)?;
+        state.serialize_field("geocode_status", &self.geocode_status)?;
+        state.serialize_field("coordinate_flags", &self.coordinate_flags)?;
+        state.serialize_field("subdivision_code", &self.subdivision_code)?;
+        state.serialize_field("admin_areas", &self.admin_areas)?;
         state.serialize_field("state", &self.state)?;
         state.serialize_field("full_address", &self.full_address)?;
         state.serialize_field("street_name", &self.street_name)?;
+        state.serialize_field("address_derived", &self.address_derived)?;
+        state.serialize_field("postcode_valid", &self.postcode_valid)?;
+        state.serialize_field("poi_ref", &self.poi_ref)?;
+        state.serialize_field("branch", &self.branch)?;
+        state.serialize_field("email", &self.email)?;
+        state.serialize_field("contact_twitter", &self.contact_twitter)?;
+        state.serialize_field("contact_facebook", &self.contact_facebook)?;
+        state.serialize_field("image", &self.image)?;
+        state.serialize_field("located_in", &self.located_in)?;
+        state.serialize_field("located_in_wikidata_id", &self.located_in_wikidata_id)?;
+        state.serialize_field("nsi_id", &self.nsi_id)?;
+        state.serialize_field("end_date", &self.end_date)?;
+        state.serialize_field("operator", &self.operator)?;
+        state.serialize_field("operator_wikidata_id", &self.operator_wikidata_id)?;
+        state.serialize_field("operator_id", &self.operator_id)?;
+        state.serialize_field("tags", &self.tags)?;
+        state.serialize_field("category_id", &self.category_id)?;
+        state.serialize_field("feature_id", &self.feature_id)?;
+        state.serialize_field("run_id", &self.run_id)?;
+        state.serialize_field("created_at", &self.created_at)?;
+        state.serialize_field("updated_at", &self.updated_at)?;
+        state.serialize_field("deleted_at", &self.deleted_at)?;
         state.end()
     }
 }
@@ -0,0 +1,126 @@
+use crate::model::{Brand, BrandOperator, DatabaseState, Operator, OperatorBrand, Poi};
+use actix_web::{
+    HttpResponse, Responder, get,
+    web::{Data, Path, Query},
+};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct OperatorPoisQuery {
+    limit: Option<i64>,
+}
+
+#[get("/operator/{id}")]
+async fn get_operator_by_id(state: Data<DatabaseState>, path: Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    debug!("Getting operator with id: {}", id);
+    match sqlx::query_as::<_, Operator>("SELECT * FROM operator WHERE id = $1")
+        .bind(id)
+        .fetch_one(&state.brand_db)
+        .await
+    {
+        Err(why) => {
+            error!("No operator found with id: {}, error: {}", id, why);
+            HttpResponse::NotFound()
+                .body(format!("No operator found with id: {}, error: {}", id, why))
+        }
+        Ok(operator) => {
+            info!("Operator found with id: {}", id);
+            HttpResponse::Ok().json(operator)
+        }
+    }
+}
+
+#[get("/operator/{id}/brands")]
+async fn get_operator_brands(state: Data<DatabaseState>, path: Path<i32>) -> impl Responder {
+    let id = path.into_inner();
+    debug!("Getting the brands of the operator with id: {}", id);
+    // The POIs and the brands live in separate databases, they can't be joined.
+    let pairs = match sqlx::query_as::<_, BrandOperator>(
+        "SELECT * FROM brand_operator WHERE operator_id = $1 ORDER BY poi_count DESC",
+    )
+    .bind(id)
+    .fetch_all(&state.poi_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while getting the brands of operator {}: {}", id, why);
+            return HttpResponse::InternalServerError().body(format!(
+                "Error while getting the brands of operator {}: {}",
+                id, why
+            ));
+        }
+        Ok(pairs) => pairs,
+    };
+
+    let brand_ids: Vec<i32> = pairs.iter().map(|pair| pair.brand_id).collect();
+    match sqlx::query_as::<_, Brand>("SELECT * FROM brand WHERE id = ANY($1)")
+        .bind(&brand_ids)
+        .fetch_all(&state.brand_db)
+        .await
+    {
+        Err(why) => {
+            error!("Error while getting the brands of operator {}: {}", id, why);
+            HttpResponse::InternalServerError().body(format!(
+                "Error while getting the brands of operator {}: {}",
+                id, why
+            ))
+        }
+        Ok(mut brands) => {
+            let mut operator_brands = vec![];
+            for pair in pairs {
+                if let Some(index) = brands.iter().position(|brand| brand.id == pair.brand_id) {
+                    operator_brands.push(OperatorBrand {
+                        brand: brands.swap_remove(index),
+                        poi_count: pair.poi_count,
+                    });
+                }
+            }
+            info!(
+                "Found {} brands for the operator with id: {}",
+                operator_brands.len(),
+                id
+            );
+            HttpResponse::Ok().json(operator_brands)
+        }
+    }
+}
+
+#[get("/operator/{id}/pois")]
+async fn get_operator_pois(
+    state: Data<DatabaseState>,
+    path: Path<i32>,
+    query: Query<OperatorPoisQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let limit = query.limit.unwrap_or(15);
+    let max_limit = 100;
+    debug!("Getting {} POIs of the operator with id: {}", limit, id);
+
+    if limit > max_limit {
+        warn!("Requested limit {} exceeds max limit {}", limit, max_limit);
+        return HttpResponse::BadRequest().body(format!("Limit must be less than {}", max_limit));
+    }
+
+    match sqlx::query_as::<_, Poi>(
+        "SELECT * FROM poi WHERE operator_id = $1 AND deleted_at IS NULL ORDER BY id LIMIT $2",
+    )
+    .bind(id)
+    .bind(limit)
+    .fetch_all(&state.poi_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while getting the POIs of operator {}: {}", id, why);
+            HttpResponse::InternalServerError().body(format!(
+                "Error while getting the POIs of operator {}: {}",
+                id, why
+            ))
+        }
+        Ok(pois) => {
+            info!("Found {} POIs for the operator with id: {}", pois.len(), id);
+            HttpResponse::Ok().json(pois)
+        }
+    }
+}
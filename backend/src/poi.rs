@@ -1,9 +1,169 @@
 use crate::model::{DatabaseState, Poi};
 use actix_web::{
     HttpResponse, Responder, get,
-    web::{Data, Path},
+    web::{Data, Path, Query},
 };
+use chrono::{DateTime, FixedOffset, Utc};
 use log::{error, info, warn};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+
+#[derive(Deserialize, Debug)]
+pub struct PoiFilters {
+    /// A raw ATP tag, `key:value` to match its value or `key` to only require it,
+    /// e.g. `shop:supermarket` or `fuel:diesel:yes`.
+    tag: Option<String>,
+    /// A category, either `key=value` like `amenity=pharmacy` or only its value.
+    category: Option<String>,
+    /// An ISO 3166-1 alpha-2 country code.
+    country: Option<String>,
+    /// An ISO 3166-2 subdivision code, e.g. `US-TX` or `FR-IDF`.
+    region: Option<String>,
+    /// A coordinate quality flag, e.g. `swapped` or `null_island`.
+    flag: Option<String>,
+    /// Only the POIs with at least one coordinate flag, or with none when `false`.
+    flagged: Option<bool>,
+    /// The host of the store page, e.g. `www.example.com`.
+    host: Option<String>,
+    /// Only the POIs open at that time, in their own time zone, e.g. `2025-03-14T18:30:00Z`.
+    open_at: Option<DateTime<FixedOffset>>,
+    /// Only the POIs open right now, `open_at` wins when both are given.
+    open_now: Option<bool>,
+    limit: Option<i64>,
+}
+
+// `opening_week` holds the opening times as minutes since Monday midnight, local time.
+fn push_open_filter(query: &mut QueryBuilder<'_, Postgres>, at: DateTime<Utc>) {
+    query
+        .push(
+            " AND timezone IS NOT NULL AND opening_week @> (
+                SELECT ((EXTRACT(ISODOW FROM local_time) - 1) * 1440
+                    + EXTRACT(HOUR FROM local_time) * 60
+                    + EXTRACT(MINUTE FROM local_time))::int
+                FROM (SELECT ",
+        )
+        .push_bind(at)
+        .push(" AT TIME ZONE timezone AS local_time) AS open_at)");
+}
+
+// Keys can hold colons themselves, the value is what follows the last one. A trailing
+// colon asks for the key alone, e.g. `fuel:diesel:`.
+fn split_tag(tag: &str) -> (&str, Option<&str>) {
+    match tag.strip_suffix(':') {
+        Some(key) => (key, None),
+        None => match tag.rsplit_once(':') {
+            Some((key, value)) => (key, Some(value)),
+            None => (tag, None),
+        },
+    }
+}
+
+fn push_tag_filter(query: &mut QueryBuilder<'_, Postgres>, tag: &str) {
+    match split_tag(tag) {
+        (key, Some(value)) => {
+            query
+                .push(" AND tags @> jsonb_build_object(")
+                .push_bind(key.to_string())
+                .push("::text, ")
+                .push_bind(value.to_string())
+                .push("::text)");
+        }
+        (key, None) => {
+            query.push(" AND tags ? ").push_bind(key.to_string());
+        }
+    }
+}
+
+fn push_category_filter(query: &mut QueryBuilder<'_, Postgres>, category: &str) {
+    query.push(" AND category_id IN (SELECT id FROM category WHERE ");
+    match category.split_once('=') {
+        Some((key, value)) => {
+            query
+                .push("key = ")
+                .push_bind(key.to_string())
+                .push(" AND value = ")
+                .push_bind(value.to_lowercase());
+        }
+        None => {
+            query.push("value = ").push_bind(category.to_lowercase());
+        }
+    }
+    query.push(")");
+}
+
+#[get("/poi")]
+async fn search_pois(state: Data<DatabaseState>, filters: Query<PoiFilters>) -> impl Responder {
+    let filters = filters.into_inner();
+    let limit = filters.limit.unwrap_or(15);
+    let max_limit = 100;
+    info!(
+        "Received request to search POIs with filters: {:?}",
+        filters
+    );
+
+    if limit > max_limit {
+        warn!(
+            "Requested limit {} exceeds max limit {}. Returning BadRequest.",
+            limit, max_limit
+        );
+        return HttpResponse::BadRequest().body(format!("Limit must be less than {}", max_limit));
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM poi WHERE deleted_at IS NULL");
+    if let Some(tag) = &filters.tag {
+        push_tag_filter(&mut query, tag);
+    }
+    if let Some(category) = &filters.category {
+        push_category_filter(&mut query, category);
+    }
+    if let Some(country) = &filters.country {
+        query
+            .push(" AND country_code = ")
+            .push_bind(country.to_uppercase());
+    }
+    if let Some(region) = &filters.region {
+        query
+            .push(" AND subdivision_code = ")
+            .push_bind(region.to_uppercase());
+    }
+    if let Some(flag) = &filters.flag {
+        query
+            .push(" AND coordinate_flags @> ARRAY[")
+            .push_bind(flag.to_lowercase())
+            .push("]::text[]");
+    }
+    if let Some(flagged) = filters.flagged {
+        query.push(match flagged {
+            true => " AND coordinate_flags <> '{}'",
+            false => " AND coordinate_flags = '{}'",
+        });
+    }
+    if let Some(host) = &filters.host {
+        query
+            .push(" AND website_host = ")
+            .push_bind(host.to_lowercase());
+    }
+    let open_at = match (filters.open_at, filters.open_now) {
+        (Some(value), _) => Some(value.with_timezone(&Utc)),
+        (None, Some(true)) => Some(Utc::now()),
+        (None, _) => None,
+    };
+    if let Some(open_at) = open_at {
+        push_open_filter(&mut query, open_at);
+    }
+    query.push(" ORDER BY id LIMIT ").push_bind(limit);
+
+    match query.build_query_as::<Poi>().fetch_all(&state.poi_db).await {
+        Err(why) => {
+            error!("Error while searching POIs: {}", why);
+            HttpResponse::InternalServerError().body(format!("Error while searching POIs: {}", why))
+        }
+        Ok(pois) => {
+            info!("Successfully retrieved {} POIs.", pois.len());
+            HttpResponse::Ok().json(pois)
+        }
+    }
+}
 
 #[get("/poi/{id}")]
 async fn get_poi_by_id(state: Data<DatabaseState>, path: Path<i32>) -> impl Responder {
@@ -41,7 +201,7 @@ async fn get_random_pois(state: Data<DatabaseState>, path: Path<i64>) -> impl Re
         return HttpResponse::BadRequest().body(format!("Limit must be less than {}", max_limit));
     }
 
-    match sqlx::query_as::<_, Poi>("SELECT * FROM poi LIMIT $1")
+    match sqlx::query_as::<_, Poi>("SELECT * FROM poi WHERE deleted_at IS NULL LIMIT $1")
         .bind(limit)
         .fetch_all(&state.poi_db)
         .await
@@ -65,10 +225,12 @@ async fn get_poi_count_for_brand_id(state: Data<DatabaseState>, path: Path<i64>)
         brand_id
     );
 
-    match sqlx::query_scalar::<_, i64>("SELECT COUNT(1) FROM poi WHERE brand_id = $1")
-        .bind(brand_id)
-        .fetch_one(&state.poi_db)
-        .await
+    match sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(1) FROM poi WHERE brand_id = $1 AND deleted_at IS NULL",
+    )
+    .bind(brand_id)
+    .fetch_one(&state.poi_db)
+    .await
     {
         Err(why) => {
             error!(
@@ -86,3 +248,16 @@ async fn get_poi_count_for_brand_id(state: Data<DatabaseState>, path: Path<i64>)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tag() {
+        assert_eq!(split_tag("shop:supermarket"), ("shop", Some("supermarket")));
+        assert_eq!(split_tag("shop"), ("shop", None));
+        assert_eq!(split_tag("fuel:diesel:yes"), ("fuel:diesel", Some("yes")));
+        assert_eq!(split_tag("fuel:diesel:"), ("fuel:diesel", None));
+    }
+}
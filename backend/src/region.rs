@@ -0,0 +1,53 @@
+use crate::model::{DatabaseState, Region};
+use actix_web::{
+    HttpResponse, Responder, get,
+    web::{Data, Query},
+};
+use log::{debug, error, info};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+
+#[derive(Deserialize, Debug)]
+pub struct RegionFilters {
+    /// An ISO 3166-1 alpha-2 country code, to only list its subdivisions.
+    country: Option<String>,
+    /// Only count the POIs of that brand.
+    brand_id: Option<i32>,
+}
+
+#[get("/region")]
+async fn get_regions(state: Data<DatabaseState>, filters: Query<RegionFilters>) -> impl Responder {
+    let filters = filters.into_inner();
+    debug!("Getting regions with filters: {:?}", filters);
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT subdivision_code, country_code, COUNT(1) AS poi_count
+        FROM poi
+        WHERE deleted_at IS NULL AND subdivision_code IS NOT NULL",
+    );
+    if let Some(country) = &filters.country {
+        query
+            .push(" AND country_code = ")
+            .push_bind(country.to_uppercase());
+    }
+    if let Some(brand_id) = filters.brand_id {
+        query.push(" AND brand_id = ").push_bind(brand_id);
+    }
+    query.push(" GROUP BY subdivision_code, country_code ORDER BY subdivision_code");
+
+    match query
+        .build_query_as::<Region>()
+        .fetch_all(&state.poi_db)
+        .await
+    {
+        Err(why) => {
+            error!("Error while getting regions: {}", why);
+            HttpResponse::InternalServerError()
+                .body(format!("Error while getting regions: {}", why))
+        }
+        Ok(regions) => {
+            info!("Successfully retrieved {} regions", regions.len());
+            HttpResponse::Ok().json(regions)
+        }
+    }
+}
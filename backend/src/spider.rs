@@ -0,0 +1,113 @@
+use crate::model::{DatabaseState, Poi, Spider};
+use actix_web::{
+    HttpResponse, Responder, get,
+    web::{Data, Path, Query},
+};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+
+#[derive(Deserialize, Debug)]
+pub struct SpiderFilters {
+    /// The outcome of the last run for the spider: `ingested`, `skipped` or `failed`.
+    status: Option<String>,
+    /// Only the spiders whose last feature count is below the one of the run before.
+    shrinking: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SpiderPoisQuery {
+    limit: Option<i64>,
+}
+
+#[get("/spider")]
+async fn get_spiders(state: Data<DatabaseState>, filters: Query<SpiderFilters>) -> impl Responder {
+    let filters = filters.into_inner();
+    debug!("Getting spiders with filters: {:?}", filters);
+
+    let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM spider WHERE TRUE");
+    if let Some(status) = &filters.status {
+        query
+            .push(" AND status = ")
+            .push_bind(status.to_lowercase());
+    }
+    if filters.shrinking.unwrap_or(false) {
+        query.push(" AND feature_count < previous_feature_count");
+    }
+    query.push(" ORDER BY id");
+
+    match query
+        .build_query_as::<Spider>()
+        .fetch_all(&state.poi_db)
+        .await
+    {
+        Err(why) => {
+            error!("Error while getting spiders: {}", why);
+            HttpResponse::InternalServerError()
+                .body(format!("Error while getting spiders: {}", why))
+        }
+        Ok(spiders) => {
+            info!("Successfully retrieved {} spiders", spiders.len());
+            HttpResponse::Ok().json(spiders)
+        }
+    }
+}
+
+#[get("/spider/{id}")]
+async fn get_spider_by_id(state: Data<DatabaseState>, path: Path<String>) -> impl Responder {
+    let id = path.into_inner();
+    debug!("Getting spider with id: {}", id);
+    match sqlx::query_as::<_, Spider>("SELECT * FROM spider WHERE id = $1")
+        .bind(&id)
+        .fetch_one(&state.poi_db)
+        .await
+    {
+        Err(why) => {
+            error!("No spider found with id: {}, error: {}", id, why);
+            HttpResponse::NotFound()
+                .body(format!("No spider found with id: {}, error: {}", id, why))
+        }
+        Ok(spider) => {
+            info!("Spider found with id: {}", id);
+            HttpResponse::Ok().json(spider)
+        }
+    }
+}
+
+#[get("/spider/{id}/pois")]
+async fn get_spider_pois(
+    state: Data<DatabaseState>,
+    path: Path<String>,
+    query: Query<SpiderPoisQuery>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let limit = query.limit.unwrap_or(15);
+    let max_limit = 100;
+    debug!("Getting {} POIs of the spider with id: {}", limit, id);
+
+    if limit > max_limit {
+        warn!("Requested limit {} exceeds max limit {}", limit, max_limit);
+        return HttpResponse::BadRequest().body(format!("Limit must be less than {}", max_limit));
+    }
+
+    match sqlx::query_as::<_, Poi>(
+        "SELECT * FROM poi WHERE spider_id = $1 AND deleted_at IS NULL ORDER BY id LIMIT $2",
+    )
+    .bind(&id)
+    .bind(limit)
+    .fetch_all(&state.poi_db)
+    .await
+    {
+        Err(why) => {
+            error!("Error while getting the POIs of spider {}: {}", id, why);
+            HttpResponse::InternalServerError().body(format!(
+                "Error while getting the POIs of spider {}: {}",
+                id, why
+            ))
+        }
+        Ok(pois) => {
+            info!("Found {} POIs for the spider with id: {}", pois.len(), id);
+            HttpResponse::Ok().json(pois)
+        }
+    }
+}
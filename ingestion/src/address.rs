@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Postcode formats by ISO 3166-1 alpha-2 country code.
+const POSTCODE_FORMATS: [(&str, &str); 38] = [
+    ("AT", r"\d{4}"),
+    ("AU", r"\d{4}"),
+    ("BE", r"\d{4}"),
+    ("BR", r"\d{5}-?\d{3}"),
+    ("CA", r"[A-Z]\d[A-Z] ?\d[A-Z]\d"),
+    ("CH", r"\d{4}"),
+    ("CN", r"\d{6}"),
+    ("CZ", r"\d{3} ?\d{2}"),
+    ("DE", r"\d{5}"),
+    ("DK", r"\d{4}"),
+    ("EE", r"\d{5}"),
+    ("ES", r"\d{5}"),
+    ("FI", r"\d{5}"),
+    ("FR", r"\d{5}"),
+    ("GB", r"[A-Z]{1,2}\d[A-Z\d]? ?\d[A-Z]{2}"),
+    ("GR", r"\d{3} ?\d{2}"),
+    ("HR", r"\d{5}"),
+    ("HU", r"\d{4}"),
+    ("IE", r"[A-Z]\d[\dW] ?[A-Z\d]{4}"),
+    ("IN", r"\d{6}"),
+    ("IT", r"\d{5}"),
+    ("JP", r"\d{3}-?\d{4}"),
+    ("LT", r"(LT-)?\d{5}"),
+    ("LU", r"(L-)?\d{4}"),
+    ("LV", r"(LV-)?\d{4}"),
+    ("MX", r"\d{5}"),
+    ("NL", r"\d{4} ?[A-Z]{2}"),
+    ("NO", r"\d{4}"),
+    ("NZ", r"\d{4}"),
+    ("PL", r"\d{2}-\d{3}"),
+    ("PT", r"\d{4}-\d{3}"),
+    ("RO", r"\d{6}"),
+    ("RU", r"\d{6}"),
+    ("SE", r"\d{3} ?\d{2}"),
+    ("SI", r"\d{4}"),
+    ("SK", r"\d{3} ?\d{2}"),
+    ("US", r"\d{5}(-\d{4})?"),
+    ("ZA", r"\d{4}"),
+];
+
+/// Countries where the house number comes after the street name.
+const NUMBER_AFTER_STREET: [&str; 21] = [
+    "AT", "BE", "CH", "CZ", "DE", "DK", "EE", "ES", "FI", "HR", "HU", "IT", "LT", "LV", "NL", "NO",
+    "PL", "PT", "SE", "SI", "SK",
+];
+
+/// Countries writing the city before the state and the postcode, e.g. `Springfield, IL 62704`.
+const CITY_BEFORE_POSTCODE: [&str; 6] = ["AU", "CA", "GB", "IE", "NZ", "US"];
+
+/// Countries whose addresses name a state, with a short code, before the postcode.
+const STATE_BEFORE_POSTCODE: [&str; 3] = ["AU", "CA", "US"];
+
+lazy_static! {
+    /// The postcode of a country, on its own.
+    static ref POSTCODES: HashMap<&'static str, Regex> = POSTCODE_FORMATS
+        .iter()
+        .map(|(country, format)| {
+            let regex = Regex::new(&format!("^(?:{})$", format)).expect("invalid postcode format");
+            (*country, regex)
+        })
+        .collect();
+    /// The postcode of a country, within a part of a full address.
+    static ref POSTCODES_IN_TEXT: HashMap<&'static str, Regex> = POSTCODE_FORMATS
+        .iter()
+        .map(|(country, format)| {
+            let regex = Regex::new(&format!(r"(?i)\b(?:{})\b", format)).expect("invalid postcode format");
+            (*country, regex)
+        })
+        .collect();
+    static ref NUMBER_FIRST: Regex =
+        Regex::new(r"^(\d+[A-Za-z]?(?:[-/]\d+[A-Za-z]?)?),?\s+(\d*[^\W\d_].*)$").unwrap();
+    static ref NUMBER_LAST: Regex =
+        Regex::new(r"^(\D.*?),?\s+(\d+[A-Za-z]?(?:[-/]\d+[A-Za-z]?)?)$").unwrap();
+}
+
+/// The address of a POI, from its `addr:*` properties.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Address {
+    pub full_address: Option<String>,
+    pub street_address: Option<String>,
+    pub house_number: Option<String>,
+    pub street_name: Option<String>,
+    pub city: Option<String>,
+    pub zipcode: Option<String>,
+    pub state: Option<String>,
+    /// The fields filled from the other ones rather than scraped, e.g. `city`.
+    pub derived: Vec<&'static str>,
+    /// Whether `zipcode` has the format of the country, `None` when either is unknown.
+    pub postcode_valid: Option<bool>,
+}
+
+/// What could be read out of a full address.
+#[derive(Debug, Default, PartialEq)]
+struct ParsedAddress {
+    house_number: Option<String>,
+    street_name: Option<String>,
+    street_address: Option<String>,
+    city: Option<String>,
+    zipcode: Option<String>,
+    state: Option<String>,
+}
+
+impl Address {
+    /// Cleans the address up and fills its missing fields from the other ones.
+    ///
+    /// Values are trimmed, their spaces collapsed and the ones written in capitals only are
+    /// title cased. Missing parts are parsed out of `full_address`, and a missing
+    /// `full_address` is composed from the parts, in the order of `country_code`.
+    pub fn normalize(mut self, country_code: &str) -> Address {
+        for field in [
+            &mut self.full_address,
+            &mut self.street_address,
+            &mut self.house_number,
+            &mut self.street_name,
+            &mut self.city,
+            &mut self.zipcode,
+            &mut self.state,
+        ] {
+            *field = field.as_deref().and_then(clean);
+        }
+        self.zipcode = self.zipcode.map(|value| value.to_uppercase());
+        self.city = self.city.map(|value| title_case_capitals(&value));
+        self.street_name = self.street_name.map(|value| title_case_capitals(&value));
+
+        if let Some(full_address) = self.full_address.clone() {
+            let parsed = parse_full_address(&full_address, country_code);
+            self.fill("house_number", parsed.house_number, |address| {
+                &mut address.house_number
+            });
+            self.fill("street_name", parsed.street_name, |address| {
+                &mut address.street_name
+            });
+            self.fill("street_address", parsed.street_address, |address| {
+                &mut address.street_address
+            });
+            self.fill("city", parsed.city, |address| &mut address.city);
+            self.fill("zipcode", parsed.zipcode, |address| &mut address.zipcode);
+            self.fill("state", parsed.state, |address| &mut address.state);
+        }
+        if let Some(street_address) = self.street_address.clone() {
+            let (house_number, street_name) = split_street(&street_address);
+            self.fill("house_number", house_number, |address| {
+                &mut address.house_number
+            });
+            self.fill("street_name", street_name, |address| {
+                &mut address.street_name
+            });
+        }
+        let street_address = self.compose_street(country_code);
+        self.fill("street_address", street_address, |address| {
+            &mut address.street_address
+        });
+        let full_address = self.compose_full_address(country_code);
+        self.fill("full_address", full_address, |address| {
+            &mut address.full_address
+        });
+
+        self.postcode_valid = self
+            .zipcode
+            .as_ref()
+            .and_then(|zipcode| Some(POSTCODES.get(country_code)?.is_match(zipcode)));
+        self
+    }
+
+    /// Sets a missing field to a derived value and records it.
+    fn fill(
+        &mut self,
+        name: &'static str,
+        value: Option<String>,
+        field: impl Fn(&mut Address) -> &mut Option<String>,
+    ) {
+        let field = field(self);
+        if field.is_some() || value.is_none() {
+            return;
+        }
+        *field = value;
+        if !self.derived.contains(&name) {
+            self.derived.push(name);
+        }
+    }
+
+    fn compose_street(&self, country_code: &str) -> Option<String> {
+        let street_name = self.street_name.as_ref()?;
+        let house_number = match &self.house_number {
+            Some(value) => value,
+            None => return Some(street_name.clone()),
+        };
+        Some(match NUMBER_AFTER_STREET.contains(&country_code) {
+            true => format!("{} {}", street_name, house_number),
+            false => format!("{} {}", house_number, street_name),
+        })
+    }
+
+    fn compose_full_address(&self, country_code: &str) -> Option<String> {
+        let locality = match CITY_BEFORE_POSTCODE.contains(&country_code) {
+            true => {
+                let region = join(&[self.state.as_deref(), self.zipcode.as_deref()], " ");
+                join(&[self.city.as_deref(), region.as_deref()], ", ")
+            }
+            false => join(&[self.zipcode.as_deref(), self.city.as_deref()], " "),
+        };
+        // A postcode alone doesn't make an address.
+        self.street_address.as_ref().or(self.city.as_ref())?;
+        join(&[self.street_address.as_deref(), locality.as_deref()], ", ")
+    }
+}
+
+/// Reads the parts of a full address like `12 rue de la Paix, 75002 Paris, France`.
+///
+/// The postcode, in the format of the country, anchors the city: it shares its part or
+/// sits right before or after it. The first part is the street.
+fn parse_full_address(full_address: &str, country_code: &str) -> ParsedAddress {
+    let parts: Vec<&str> = full_address
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut parsed = ParsedAddress::default();
+    if parts.len() < 2 {
+        return parsed;
+    }
+
+    let postcode = POSTCODES_IN_TEXT.get(country_code).and_then(|regex| {
+        parts
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .find_map(|(index, part)| regex.find(part).map(|found| (index, found)))
+    });
+    let city_index = match postcode {
+        Some((index, found)) => {
+            let part = parts[index];
+            parsed.zipcode = Some(found.as_str().to_uppercase());
+            let rest = format!("{} {}", &part[..found.start()], &part[found.end()..]);
+            let rest = clean(&rest);
+            let is_state = rest.as_ref().is_some_and(|value| {
+                STATE_BEFORE_POSTCODE.contains(&country_code)
+                    && value.len() <= 3
+                    && value
+                        .chars()
+                        .all(|character| character.is_ascii_uppercase())
+            });
+            match rest {
+                Some(value) if is_state => {
+                    parsed.state = Some(value);
+                    Some(index - 1)
+                }
+                Some(value) => {
+                    parsed.city = Some(title_case_capitals(&value));
+                    None
+                }
+                None if index > 1 => Some(index - 1),
+                None => parts
+                    .get(index + 1)
+                    .filter(|part| !part.chars().any(|character| character.is_ascii_digit()))
+                    .map(|_| index + 1),
+            }
+        }
+        // Without a postcode, only `street, city` is unambiguous.
+        None if parts.len() == 2 => Some(1),
+        None => None,
+    };
+    if let Some(index) = city_index.filter(|index| *index > 0) {
+        parsed.city = clean(parts[index]).map(|value| title_case_capitals(&value));
+    }
+
+    parsed.street_address = clean(parts[0]);
+    let (house_number, street_name) = split_street(parts[0]);
+    parsed.house_number = house_number;
+    parsed.street_name = street_name.map(|value| title_case_capitals(&value));
+    parsed
+}
+
+/// Splits `12 rue de la Paix` or `Hauptstraße 5` into its house number and street name.
+fn split_street(street: &str) -> (Option<String>, Option<String>) {
+    if let Some(captures) = NUMBER_FIRST.captures(street) {
+        return (clean(&captures[1]), clean(&captures[2]));
+    }
+    if let Some(captures) = NUMBER_LAST.captures(street) {
+        return (clean(&captures[2]), clean(&captures[1]));
+    }
+    match street.chars().any(|character| character.is_ascii_digit()) {
+        // `Unit 4, 12-14 High St` can't be split safely.
+        true => (None, None),
+        false => (None, clean(street)),
+    }
+}
+
+/// Trims the value and collapses its spaces, `None` when nothing is left.
+fn clean(value: &str) -> Option<String> {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!value.is_empty()).then_some(value)
+}
+
+/// `PARIS` becomes `Paris`, values with lowercase letters are left as they are.
+fn title_case_capitals(value: &str) -> String {
+    let has_letters = value.chars().any(char::is_alphabetic);
+    if !has_letters || value.chars().any(char::is_lowercase) {
+        return value.to_string();
+    }
+    let mut result = String::with_capacity(value.len());
+    let mut start_of_word = true;
+    for character in value.chars() {
+        match start_of_word {
+            true => result.extend(character.to_uppercase()),
+            false => result.extend(character.to_lowercase()),
+        }
+        start_of_word = !character.is_alphanumeric();
+    }
+    result
+}
+
+fn join(values: &[Option<&str>], separator: &str) -> Option<String> {
+    let values: Vec<&str> = values.iter().flatten().copied().collect();
+    (!values.is_empty()).then(|| values.join(separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full(value: &str) -> Address {
+        Address {
+            full_address: Some(value.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_full_address_fr() {
+        let address = full("12 rue de la Paix, 75002 PARIS, France").normalize("FR");
+        assert_eq!(address.house_number.as_deref(), Some("12"));
+        assert_eq!(address.street_name.as_deref(), Some("rue de la Paix"));
+        assert_eq!(address.zipcode.as_deref(), Some("75002"));
+        assert_eq!(address.city.as_deref(), Some("Paris"));
+        assert_eq!(address.postcode_valid, Some(true));
+        assert_eq!(
+            address.derived,
+            vec![
+                "house_number",
+                "street_name",
+                "street_address",
+                "city",
+                "zipcode"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_full_address_us() {
+        let address = full("350 5th Ave, New York, NY 10118").normalize("US");
+        assert_eq!(address.house_number.as_deref(), Some("350"));
+        assert_eq!(address.street_name.as_deref(), Some("5th Ave"));
+        assert_eq!(address.city.as_deref(), Some("New York"));
+        assert_eq!(address.state.as_deref(), Some("NY"));
+        assert_eq!(address.zipcode.as_deref(), Some("10118"));
+    }
+
+    #[test]
+    fn test_parse_full_address_de() {
+        let address = full("Hauptstraße 5a, 10115 Berlin").normalize("DE");
+        assert_eq!(address.house_number.as_deref(), Some("5a"));
+        assert_eq!(address.street_name.as_deref(), Some("Hauptstraße"));
+        assert_eq!(address.city.as_deref(), Some("Berlin"));
+    }
+
+    #[test]
+    fn test_parse_full_address_keeps_scraped_fields() {
+        let address = Address {
+            full_address: Some(String::from("12 rue de la Paix, 75002 Paris")),
+            city: Some(String::from("Paris 2e")),
+            ..Default::default()
+        }
+        .normalize("FR");
+        assert_eq!(address.city.as_deref(), Some("Paris 2e"));
+        assert!(!address.derived.contains(&"city"));
+    }
+
+    #[test]
+    fn test_compose_full_address() {
+        let address = Address {
+            house_number: Some(String::from(" 5 ")),
+            street_name: Some(String::from("HAUPTSTRASSE")),
+            city: Some(String::from("Berlin")),
+            zipcode: Some(String::from("10115")),
+            ..Default::default()
+        }
+        .normalize("DE");
+        assert_eq!(address.street_address.as_deref(), Some("Hauptstrasse 5"));
+        assert_eq!(
+            address.full_address.as_deref(),
+            Some("Hauptstrasse 5, 10115 Berlin")
+        );
+        assert_eq!(address.derived, vec!["street_address", "full_address"]);
+
+        let address = Address {
+            street_address: Some(String::from("350 5th Ave")),
+            city: Some(String::from("New York")),
+            state: Some(String::from("NY")),
+            zipcode: Some(String::from("10118")),
+            ..Default::default()
+        }
+        .normalize("US");
+        assert_eq!(
+            address.full_address.as_deref(),
+            Some("350 5th Ave, New York, NY 10118")
+        );
+
+        // Nothing to compose from a postcode alone.
+        let address = Address {
+            zipcode: Some(String::from("10118")),
+            ..Default::default()
+        }
+        .normalize("US");
+        assert_eq!(address.full_address, None);
+    }
+
+    #[test]
+    fn test_postcode_validation() {
+        let postcode = |zipcode: &str, country_code: &str| {
+            Address {
+                zipcode: Some(zipcode.to_string()),
+                ..Default::default()
+            }
+            .normalize(country_code)
+            .postcode_valid
+        };
+        assert_eq!(postcode("sw1a 1aa", "GB"), Some(true));
+        assert_eq!(postcode("12345-6789", "US"), Some(true));
+        assert_eq!(postcode("7500", "FR"), Some(false));
+        assert_eq!(postcode("1234 AB", "NL"), Some(true));
+        assert_eq!(postcode("12345", "XX"), None);
+    }
+
+    #[test]
+    fn test_title_case_capitals() {
+        assert_eq!(title_case_capitals("SAINT-DENIS"), "Saint-Denis");
+        assert_eq!(title_case_capitals("L'ISLE D'ABEAU"), "L'Isle D'Abeau");
+        assert_eq!(title_case_capitals("rue de la Paix"), "rue de la Paix");
+        assert_eq!(title_case_capitals("10118"), "10118");
+    }
+}
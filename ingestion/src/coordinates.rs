@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use geo::Point;
+
+use crate::geocoder::Geocoder;
+use crate::model::{CoordinateFlag, POI};
+
+/// How close to `(0, 0)`, in degrees, a point is taken for a missing location.
+const NULL_ISLAND_TOLERANCE: f64 = 1e-4;
+
+/// Flags the coordinates of a POI that look wrong, without fixing them.
+///
+/// `geocoded_country` is where the point falls, `address_country` the code of
+/// `addr:country`. The coordinates are only geocoded the other way round when they
+/// don't match the address, or don't make sense at all.
+pub fn check_coordinates(
+    point: &Point,
+    geocoded_country: Option<&str>,
+    address_country: Option<&str>,
+    geocoder: &dyn Geocoder,
+) -> Vec<CoordinateFlag> {
+    let (longitude, latitude) = (point.x(), point.y());
+    let mut flags = vec![];
+    if !in_range(longitude, latitude) {
+        flags.push(CoordinateFlag::OutOfRange);
+    }
+    if longitude.abs() < NULL_ISLAND_TOLERANCE && latitude.abs() < NULL_ISLAND_TOLERANCE {
+        flags.push(CoordinateFlag::NullIsland);
+        return flags;
+    }
+    let is_mismatch = matches!(
+        (geocoded_country, address_country),
+        (Some(geocoded), Some(address)) if geocoded != address
+    );
+    if is_mismatch {
+        flags.push(CoordinateFlag::CountryMismatch);
+    }
+
+    let swapped_country = || {
+        geocoder
+            .locate(&Point::new(latitude, longitude))
+            .boundary_ids
+            .pop()
+    };
+    let is_swapped = in_range(latitude, longitude)
+        && match address_country {
+            Some(address) => {
+                geocoded_country != Some(address) && swapped_country().as_deref() == Some(address)
+            }
+            None => !in_range(longitude, latitude) && swapped_country().is_some(),
+        };
+    if is_swapped {
+        flags.push(CoordinateFlag::Swapped);
+    }
+    flags.sort();
+    flags
+}
+
+/// Flags the POIs of a spider sharing their exact coordinates with another one.
+pub fn flag_duplicate_coordinates(pois: &mut [POI]) {
+    let key = |point: &Point| (point.x().to_bits(), point.y().to_bits());
+    let mut counts: HashMap<(u64, u64), usize> = HashMap::new();
+    for point in pois.iter().filter_map(|poi| poi.point.as_ref()) {
+        *counts.entry(key(point)).or_default() += 1;
+    }
+    for poi in pois.iter_mut() {
+        let is_duplicate = poi
+            .point
+            .as_ref()
+            .is_some_and(|point| counts[&key(point)] > 1);
+        if is_duplicate {
+            poi.coordinate_flags.push(CoordinateFlag::Duplicate);
+            poi.coordinate_flags.sort();
+        }
+    }
+}
+
+fn in_range(longitude: f64, latitude: f64) -> bool {
+    (-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geocoder::CountryBoundariesGeocoder;
+
+    fn check(x: f64, y: f64, geocoded: Option<&str>, address: Option<&str>) -> Vec<CoordinateFlag> {
+        check_coordinates(
+            &Point::new(x, y),
+            geocoded,
+            address,
+            &CountryBoundariesGeocoder,
+        )
+    }
+
+    #[test]
+    fn test_check_coordinates_valid() {
+        assert!(check(2.3276581, 48.8805374, Some("FR"), Some("FR")).is_empty());
+        assert!(check(2.3276581, 48.8805374, Some("FR"), None).is_empty());
+    }
+
+    #[test]
+    fn test_check_coordinates_null_island() {
+        assert_eq!(
+            check(0.0, 0.0, None, Some("FR")),
+            vec![CoordinateFlag::NullIsland]
+        );
+    }
+
+    #[test]
+    fn test_check_coordinates_country_mismatch() {
+        assert_eq!(
+            check(2.3276581, 48.8805374, Some("FR"), Some("DE")),
+            vec![CoordinateFlag::CountryMismatch]
+        );
+    }
+
+    #[test]
+    fn test_check_coordinates_swapped() {
+        // Paris the wrong way round, in the Indian Ocean.
+        assert_eq!(
+            check(48.8805374, 2.3276581, None, Some("FR")),
+            vec![CoordinateFlag::Swapped]
+        );
+        // A latitude of -95 can't be, the other way round it's Texas.
+        assert_eq!(
+            check(30.0, -95.0, None, None),
+            vec![CoordinateFlag::OutOfRange, CoordinateFlag::Swapped]
+        );
+        // Out of range either way.
+        assert_eq!(
+            check(200.0, 100.0, None, None),
+            vec![CoordinateFlag::OutOfRange]
+        );
+    }
+}
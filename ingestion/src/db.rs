@@ -1,88 +1,441 @@
+use std::collections::BTreeMap;
 use std::io::Write;
 
+use clap::ValueEnum;
 use geo::Point;
 use log::{debug, error, info};
-use postgres::{Client, NoTls};
+use postgres::types::Json;
+use postgres::{Client, NoTls, Transaction};
 
-use crate::model::{Brand, POI};
+use crate::error::IngestionError;
+use crate::model::{AtpRunInfo, Brand, Category, POI, Run, RunStats};
+use crate::poi::normalize_brand_name;
+use crate::report::{BrandChange, RunReport};
 
-pub fn get_client(url: &str) -> Client {
+pub fn get_client(url: &str) -> Result<Client, IngestionError> {
     debug!("attempting to connect to database at {}", url);
 
     match Client::connect(url, NoTls) {
         Ok(client) => {
             info!("successfully connected to database '{}'", url);
-            client
+            Ok(client)
         }
         Err(err) => {
             error!("failed to connect to database: {}", err);
-            panic!("error while creating the sql client: {}", err);
+            Err(err.into())
         }
     }
 }
 
-pub fn truncate_table(client: &mut Client, table: &str) -> Result<(), Box<dyn std::error::Error>> {
-    debug!("attempting to truncate poi table");
-    let truncate = format!("truncate table {};", table);
+/// Schema holding the tables of a run until they are swapped into place.
+const STAGING_SCHEMA: &str = "staging";
 
+/// Creates an empty copy of `table` in the staging schema and points the session at it.
+///
+/// Once this returns, unqualified references to `table` on this client resolve to the
+/// staging copy, readers of the live table are not affected until `swap_staging_tables`.
+/// With `copy_live` the staging table starts with the rows of the live one, so ids are kept.
+/// The rows are copied before the indexes are built, which is much faster than updating
+/// every index row by row.
+pub fn prepare_staging_table(
+    client: &mut Client,
+    table: &str,
+    copy_live: bool,
+) -> Result<(), IngestionError> {
+    debug!("attempting to prepare the staging table for {}", table);
     let mut transaction = client.transaction()?;
+    let like_options = if copy_live {
+        "INCLUDING ALL EXCLUDING INDEXES"
+    } else {
+        "INCLUDING ALL"
+    };
+    transaction.batch_execute(&format!(
+        "CREATE SCHEMA IF NOT EXISTS {staging};
+        DROP TABLE IF EXISTS {staging}.{table};
+        CREATE TABLE {staging}.{table} (LIKE public.{table} {like_options});",
+        staging = STAGING_SCHEMA,
+    ))?;
+    if copy_live {
+        debug!("copying the live rows of {} into staging", table);
+        transaction.execute(
+            &format!(
+                "INSERT INTO {}.{table} SELECT * FROM public.{table}",
+                STAGING_SCHEMA
+            ),
+            &[],
+        )?;
+        create_staging_indexes(&mut transaction, table)?;
+    }
+    transaction.commit()?;
 
-    debug!("executing query: {}", truncate);
-    transaction.execute(&truncate, &[])?;
+    use_staging_tables(client)?;
+    info!("successfully prepared the staging table for {}", table);
+    Ok(())
+}
 
-    debug!("committing transaction: {}", truncate);
-    transaction.commit()?;
+/// Builds the indexes and the primary key, unique and exclusion constraints of the live
+/// `table` on its staging copy, under the same names.
+fn create_staging_indexes(
+    transaction: &mut Transaction,
+    table: &str,
+) -> Result<(), IngestionError> {
+    debug!("creating the indexes of the staging table for {}", table);
+    let live_table = format!("public.{table}");
+    let constraints = transaction.query(
+        "SELECT conname::TEXT, pg_get_constraintdef(oid)
+        FROM pg_constraint
+        WHERE conrelid = $1::TEXT::regclass AND contype IN ('p', 'u', 'x')",
+        &[&live_table],
+    )?;
+    for row in constraints {
+        let (name, definition): (String, String) = (row.get(0), row.get(1));
+        transaction.batch_execute(&format!(
+            "ALTER TABLE {STAGING_SCHEMA}.{table} ADD CONSTRAINT {name} {definition}"
+        ))?;
+    }
+
+    // The indexes backing the constraints above are already there.
+    let indexes = transaction.query(
+        "SELECT pg_get_indexdef(indexrelid)
+        FROM pg_index
+        WHERE indrelid = $1::TEXT::regclass
+            AND NOT EXISTS (
+                SELECT 1 FROM pg_constraint
+                WHERE conindid = indexrelid AND contype IN ('p', 'u', 'x')
+            )",
+        &[&live_table],
+    )?;
+    for row in indexes {
+        let definition: String = row.get(0);
+        transaction.batch_execute(&definition.replacen(
+            &format!(" ON {live_table} "),
+            &format!(" ON {STAGING_SCHEMA}.{table} "),
+            1,
+        ))?;
+    }
+    Ok(())
+}
 
-    info!("successfully truncated {} table", table);
+/// Points the session at the staging tables, the search path isn't shared between
+/// connections so every writer has to call this.
+pub fn use_staging_tables(client: &mut Client) -> Result<(), IngestionError> {
+    client.batch_execute(&format!("SET search_path TO {}, public", STAGING_SCHEMA))?;
     Ok(())
 }
 
+/// Replaces the live `tables` with their staging copies in a single transaction.
+///
+/// The `id` sequence, when the table has one, is detached before the live table is
+/// dropped so the staging table, whose default already uses it, keeps handing out the same ids.
+pub fn swap_staging_tables(client: &mut Client, tables: &[&str]) -> Result<(), IngestionError> {
+    debug!("attempting to swap the staging tables for {:?}", tables);
+    let mut transaction = client.transaction()?;
+    for table in tables {
+        transaction.batch_execute(&format!(
+            "LOCK TABLE public.{table} IN ACCESS EXCLUSIVE MODE;
+            ALTER SEQUENCE IF EXISTS public.{table}_id_seq OWNED BY NONE;
+            DROP TABLE public.{table};
+            ALTER TABLE {staging}.{table} SET SCHEMA public;
+            ALTER SEQUENCE IF EXISTS public.{table}_id_seq OWNED BY public.{table}.id;",
+            staging = STAGING_SCHEMA,
+        ))?;
+    }
+    transaction.commit()?;
+    info!("successfully swapped the staging tables into {:?}", tables);
+    Ok(())
+}
+
+/// Columns written for every POI, in the order produced by `write_poi_rows`.
+const POI_COLUMNS: [&str; 47] = [
+    "spider_id",
+    "feature_id",
+    "run_id",
+    "poi_name",
+    "brand_id",
+    "website",
+    "website_host",
+    "website_source",
+    "opening_hours",
+    "opening_schedule",
+    "opening_week",
+    "opening_hours_error",
+    "timezone",
+    "phone",
+    "phones",
+    "invalid_phones",
+    "point",
+    "city",
+    "zipcode",
+    "house_number",
+    "street_address",
+    "country",
+    "state",
+    "full_address",
+    "street_name",
+    "address_derived",
+    "postcode_valid",
+    "country_code",
+    "geocode_status",
+    "coordinate_flags",
+    "subdivision_code",
+    "admin_areas",
+    "poi_ref",
+    "branch",
+    "email",
+    "contact_twitter",
+    "contact_facebook",
+    "image",
+    "located_in",
+    "located_in_wikidata_id",
+    "nsi_id",
+    "end_date",
+    "operator",
+    "operator_wikidata_id",
+    "tags",
+    "operator_id",
+    "category_id",
+];
+
+/// How a run is written into the `poi` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IngestionMode {
+    /// Reload every file into an empty table, ids change on every run.
+    Full,
+    /// Match features on (spider_id, feature_id) and only write what changed.
+    Incremental,
+}
+
+/// Row counts of a single spider synchronisation.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    pub inserted: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
 pub fn ingest_poi_into_db(
     client: &mut Client,
     pois: Vec<POI>,
-    brand_id: i32,
-) -> Result<(), Box<dyn std::error::Error>> {
+    references: &References,
+    run_id: i32,
+) -> Result<(), IngestionError> {
+    let columns = POI_COLUMNS.join(", ");
+    let category_ids = ingest_categories_into_db(client, &pois)?;
+    let mut transaction = client.transaction()?;
+    create_incoming_table(&mut transaction, &columns)?;
+    let mut writer = transaction.copy_in(&format!("COPY poi_incoming ({}) FROM STDIN", columns))?;
+
+    let buffer = write_poi_rows(pois, references, &category_ids, run_id);
+
+    // Write the entire buffer at once
+    writer.write_all(buffer.as_bytes())?;
+
+    // Finish the COPY operation
+    writer.finish()?;
+
+    // A feature id repeated in the file would break the unique key of `poi`, keep the last.
+    transaction.execute(
+        &format!(
+            "INSERT INTO poi ({columns})
+            SELECT DISTINCT ON (spider_id, feature_id) {columns}
+            FROM poi_incoming
+            ORDER BY spider_id, feature_id, ordinal DESC"
+        ),
+        &[],
+    )?;
+
+    // Commit the transaction
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Creates the temporary table the POIs of a file are copied into, with an `ordinal`
+/// numbering the rows in file order so the same one of a repeated feature id always wins.
+fn create_incoming_table(
+    transaction: &mut Transaction,
+    columns: &str,
+) -> Result<(), IngestionError> {
+    transaction.batch_execute(&format!(
+        "CREATE TEMP TABLE poi_incoming ON COMMIT DROP AS SELECT {} FROM poi WITH NO DATA;
+        ALTER TABLE poi_incoming ADD COLUMN ordinal INTEGER GENERATED ALWAYS AS IDENTITY;",
+        columns
+    ))?;
+    Ok(())
+}
+
+/// Synchronises the POIs of one spider with the rows already in the database.
+///
+/// New features are inserted, changed ones are updated in place (keeping their id)
+/// and rows of the spider that are not part of `pois` anymore are soft-deleted.
+pub fn sync_pois_into_db(
+    client: &mut Client,
+    spider_id: &str,
+    pois: Vec<POI>,
+    references: &References,
+    run_id: i32,
+) -> Result<SyncStats, IngestionError> {
+    let columns = POI_COLUMNS.join(", ");
+    let category_ids = ingest_categories_into_db(client, &pois)?;
     let mut transaction = client.transaction()?;
+
+    debug!("creating the incoming table for spider {}", spider_id);
+    create_incoming_table(&mut transaction, &columns)?;
+
+    let mut writer = transaction.copy_in(&format!("COPY poi_incoming ({}) FROM STDIN", columns))?;
+    writer.write_all(write_poi_rows(pois, references, &category_ids, run_id).as_bytes())?;
+    writer.finish()?;
+
+    // Only touch rows whose content actually changed, so `updated_at` and `run_id`
+    // tell which run the current values come from.
+    let updates = POI_COLUMNS
+        .iter()
+        .filter(|column| !matches!(**column, "spider_id" | "feature_id"))
+        .map(|column| format!("{} = EXCLUDED.{}", column, column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let compared = POI_COLUMNS
+        .iter()
+        .filter(|column| **column != "run_id")
+        .collect::<Vec<_>>();
+    let current = compared
+        .iter()
+        .map(|column| format!("poi.{}", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let excluded = compared
+        .iter()
+        .map(|column| format!("EXCLUDED.{}", column))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let upsert = format!(
+        "INSERT INTO poi ({columns})
+        SELECT DISTINCT ON (spider_id, feature_id) {columns}
+        FROM poi_incoming
+        ORDER BY spider_id, feature_id, ordinal DESC
+        ON CONFLICT (spider_id, feature_id) DO UPDATE
+        SET {updates}, updated_at = now(), deleted_at = NULL
+        WHERE ({current}, poi.deleted_at) IS DISTINCT FROM ({excluded}, NULL)
+        RETURNING (xmax = 0) AS inserted"
+    );
+    let rows = transaction.query(&upsert, &[])?;
+    let inserted = rows
+        .iter()
+        .filter(|row| row.get::<_, bool>("inserted"))
+        .count() as u64;
+
+    let delete = "
+        UPDATE poi SET deleted_at = now(), updated_at = now()
+        WHERE spider_id = $1
+        AND deleted_at IS NULL
+        AND NOT EXISTS (
+            SELECT 1 FROM poi_incoming
+            WHERE poi_incoming.spider_id = poi.spider_id
+            AND poi_incoming.feature_id = poi.feature_id
+        )";
+    let deleted = transaction.execute(delete, &[&spider_id])?;
+
+    transaction.commit()?;
+    let stats = SyncStats {
+        inserted,
+        updated: rows.len() as u64 - inserted,
+        deleted,
+    };
+    info!(
+        "spider {} synchronised: {} inserted, {} updated, {} deleted",
+        spider_id, stats.inserted, stats.updated, stats.deleted
+    );
+    Ok(stats)
+}
+
+/// Soft-deletes the POIs of every spider that was not part of the run.
+pub fn soft_delete_missing_spiders(
+    client: &mut Client,
+    seen_spiders: &[String],
+) -> Result<u64, IngestionError> {
     let query = "
-    COPY poi (
-        spider_id,
-        poi_name,
-        brand_id,
-        website,
-        opening_hours,
-        phone,
-        point,
-        city,
-        zipcode,
-        house_number,
-        street_address,
-        country,
-        state,
-        full_address,
-        street_name,
-        country_code
-    ) FROM STDIN";
-    let mut writer = transaction.copy_in(query)?;
+        UPDATE poi SET deleted_at = now(), updated_at = now()
+        WHERE deleted_at IS NULL
+        AND NOT (spider_id = ANY($1))";
+    let mut transaction = client.transaction()?;
+    let deleted = transaction.execute(query, &[&seen_spiders])?;
+    transaction.commit()?;
+    info!(
+        "{} POIs soft-deleted from spiders missing in this run",
+        deleted
+    );
+    Ok(deleted)
+}
+
+/// Ids of the brands and operators of a file in the brand database, operators by name.
+pub struct References {
+    pub brand_ids: BTreeMap<Brand, i32>,
+    pub operator_ids: BTreeMap<String, i32>,
+}
 
+/// Formats the POIs as tab separated rows for a `COPY ... FROM STDIN` of `POI_COLUMNS`.
+fn write_poi_rows(
+    pois: Vec<POI>,
+    references: &References,
+    category_ids: &BTreeMap<Category, i32>,
+    run_id: i32,
+) -> String {
     // Create a single buffer for all POIs
     // Preallocate a reasonable size
     let mut buffer = String::with_capacity(pois.len() * 256);
 
     // Process all POIs and build the complete buffer
     for poi in pois {
+        let operator_id = operator_name(&poi).and_then(|name| references.operator_ids.get(name));
         // Format each field with proper escaping and tab separation
-        buffer.push_str(&poi.spider_id);
+        buffer.push_str(&escape_field(&poi.spider_id));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.feature_id));
+        buffer.push('\t');
+        buffer.push_str(&run_id.to_string());
         buffer.push('\t');
         buffer.push_str(&escape_field(&poi.poi_name.unwrap_or_default()));
         buffer.push('\t');
-        buffer.push_str(&escape_field(&brand_id.to_string()));
+        let brand_id = poi
+            .brand
+            .as_ref()
+            .and_then(|brand| references.brand_ids.get(brand));
+        buffer.push_str(&escape_field(
+            &brand_id.map(|id| id.to_string()).unwrap_or_default(),
+        ));
         buffer.push('\t');
         buffer.push_str(&escape_field(&poi.website.unwrap_or_default()));
         buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.website_host.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(
+            poi.website_source.map_or("", |value| value.as_str()),
+        ));
+        buffer.push('\t');
         buffer.push_str(&escape_field(&poi.opening_hours.unwrap_or_default()));
         buffer.push('\t');
+        let schedule = poi.opening_schedule.as_ref();
+        buffer.push_str(&escape_field(
+            &schedule
+                .and_then(|value| serde_json::to_string(value).ok())
+                .unwrap_or_default(),
+        ));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(
+            &schedule
+                .map(|value| value.to_week_ranges())
+                .unwrap_or_default(),
+        ));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.opening_hours_error.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.timezone.unwrap_or_default()));
+        buffer.push('\t');
         buffer.push_str(&escape_field(&poi.phone.unwrap_or_default()));
         buffer.push('\t');
+        buffer.push_str(&escape_field(&format_text_array(&poi.phones)));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&format_text_array(&poi.invalid_phones)));
+        buffer.push('\t');
         buffer.push_str(&point_to_string(&poi.point));
         buffer.push('\t');
         buffer.push_str(&escape_field(&poi.city.unwrap_or_default()));
@@ -101,22 +454,77 @@ pub fn ingest_poi_into_db(
         buffer.push('\t');
         buffer.push_str(&escape_field(&poi.street_name.unwrap_or_default()));
         buffer.push('\t');
-        buffer.push_str(&escape_field(&poi.country_code));
+        buffer.push_str(&escape_field(&format_text_array(&poi.address_derived)));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(
+            poi.postcode_valid
+                .map_or("", |value| if value { "t" } else { "f" }),
+        ));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.country_code.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(poi.geocode_status.as_str());
+        buffer.push('\t');
+        let coordinate_flags: Vec<String> = poi
+            .coordinate_flags
+            .iter()
+            .map(|flag| flag.as_str().to_string())
+            .collect();
+        buffer.push_str(&escape_field(&format_text_array(&coordinate_flags)));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.subdivision_code.unwrap_or_default()));
+        buffer.push('\t');
+        let admin_areas = match poi.admin_areas.is_empty() {
+            true => String::new(),
+            false => serde_json::to_string(&poi.admin_areas).unwrap_or_default(),
+        };
+        buffer.push_str(&escape_field(&admin_areas));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.poi_ref.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.branch.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.email.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.contact_twitter.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.contact_facebook.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.image.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.located_in.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(
+            &poi.located_in_wikidata_id.unwrap_or_default(),
+        ));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.nsi_id.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.end_date.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.operator.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.operator_wikidata_id.unwrap_or_default()));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(&poi.tags));
+        buffer.push('\t');
+        buffer.push_str(&escape_field(
+            &operator_id.map(|id| id.to_string()).unwrap_or_default(),
+        ));
+        buffer.push('\t');
+        let category_id = poi
+            .category
+            .as_ref()
+            .and_then(|category| category_ids.get(category));
+        buffer.push_str(&escape_field(
+            &category_id.map(|id| id.to_string()).unwrap_or_default(),
+        ));
         buffer.push('\n');
     }
 
     // Remove any null characters from the buffer
     buffer.retain(|c| c != '\u{0000}');
-
-    // Write the entire buffer at once
-    writer.write_all(buffer.as_bytes())?;
-
-    // Finish the COPY operation
-    writer.finish()?;
-
-    // Commit the transaction
-    transaction.commit()?;
-    Ok(())
+    buffer
 }
 
 fn point_to_string(point: &Option<Point>) -> String {
@@ -126,6 +534,15 @@ fn point_to_string(point: &Option<Point>) -> String {
     }
 }
 
+/// Formats `values` as a PostgreSQL array literal, every element quoted.
+fn format_text_array(values: &[String]) -> String {
+    let elements: Vec<String> = values
+        .iter()
+        .map(|value| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
 // Helper function to escape fields for PostgreSQL COPY
 fn escape_field(field: &str) -> String {
     if field.is_empty() {
@@ -139,18 +556,400 @@ fn escape_field(field: &str) -> String {
     }
 }
 
-pub fn ingest_brand_into_db(
+/// Makes sure the categories of `pois` exist and returns their ids.
+///
+/// The `category` table only grows and isn't staged, each category is committed on its
+/// own so writers of other files never wait on the transaction of this one.
+pub fn ingest_categories_into_db(
     client: &mut Client,
-    brand: Brand,
-) -> Result<i32, Box<dyn std::error::Error>> {
+    pois: &[POI],
+) -> Result<BTreeMap<Category, i32>, IngestionError> {
     let query = "
-        INSERT INTO brand (name, wikidata_id)
+        INSERT INTO public.category (key, value)
         VALUES ($1, $2)
-        ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+        ON CONFLICT (key, value) DO UPDATE SET key = EXCLUDED.key
         RETURNING id";
+    let mut category_ids = BTreeMap::new();
+    for category in pois.iter().filter_map(|poi| poi.category.as_ref()) {
+        if category_ids.contains_key(category) {
+            continue;
+        }
+        let row = client.query_one(query, &[&category.key, &category.value])?;
+        category_ids.insert(category.clone(), row.get("id"));
+    }
+    Ok(category_ids)
+}
+
+/// Makes sure the brands of `pois` exist and returns their ids.
+///
+/// A brand with a Wikidata id is the brand with that id, whatever its name. A brand with
+/// only a name is the single identified brand known under that name, spelling variants
+/// included, or else the brand known by that name only. Every name a brand is seen
+/// under is kept as an alias. The changes made along the way are added to `changes`.
+///
+/// The brands of a file are resolved in a single transaction, holding a lock so that
+/// writers don't resolve the same names concurrently.
+pub fn ingest_brands_into_db(
+    client: &mut Client,
+    pois: &[POI],
+    changes: &mut Vec<BrandChange>,
+) -> Result<BTreeMap<Brand, i32>, IngestionError> {
+    let mut brand_ids = BTreeMap::new();
     let mut transaction = client.transaction()?;
-    let row = transaction.query_one(query, &[&brand.name, &brand.wikidata_id])?;
-    let id: i32 = row.get("id");
+    transaction.execute("SELECT pg_advisory_xact_lock(hashtext('brand'))", &[])?;
+    for brand in pois.iter().filter_map(|poi| poi.brand.as_ref()) {
+        if brand_ids.contains_key(brand) {
+            continue;
+        }
+        let normalized_name = normalize_brand_name(&brand.name);
+        let id = match &brand.wikidata_id {
+            Some(wikidata_id) => resolve_identified_brand(
+                &mut transaction,
+                &brand.name,
+                &normalized_name,
+                wikidata_id,
+                changes,
+            )?,
+            None => resolve_named_brand(&mut transaction, &brand.name, &normalized_name)?,
+        };
+        transaction.execute(
+            "INSERT INTO brand_alias (brand_id, name, normalized_name)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING",
+            &[&id, &brand.name, &normalized_name],
+        )?;
+        brand_ids.insert(brand.clone(), id);
+    }
     transaction.commit()?;
+    Ok(brand_ids)
+}
+
+/// Ids of the brands with a Wikidata id known under `normalized_name`.
+fn find_identified_brands(
+    transaction: &mut Transaction,
+    normalized_name: &str,
+) -> Result<Vec<i32>, IngestionError> {
+    let query = "
+        SELECT DISTINCT brand.id
+        FROM brand_alias
+        JOIN brand ON brand.id = brand_alias.brand_id
+        WHERE brand_alias.normalized_name = $1 AND brand.wikidata_id IS NOT NULL";
+    let rows = transaction.query(query, &[&normalized_name])?;
+    Ok(rows.iter().map(|row| row.get("id")).collect())
+}
+
+/// Id of the brand known by `normalized_name` only, without a Wikidata id.
+fn find_unidentified_brand(
+    transaction: &mut Transaction,
+    normalized_name: &str,
+) -> Result<Option<i32>, IngestionError> {
+    let query = "
+        SELECT id FROM brand
+        WHERE normalized_name = $1 AND wikidata_id IS NULL AND merged_into_id IS NULL";
+    let row = transaction.query_opt(query, &[&normalized_name])?;
+    Ok(row.map(|row| row.get("id")))
+}
+
+fn resolve_identified_brand(
+    transaction: &mut Transaction,
+    name: &str,
+    normalized_name: &str,
+    wikidata_id: &str,
+    changes: &mut Vec<BrandChange>,
+) -> Result<i32, IngestionError> {
+    let existing: Option<i32> = transaction
+        .query_opt(
+            "SELECT id FROM brand WHERE wikidata_id = $1",
+            &[&wikidata_id],
+        )?
+        .map(|row| row.get("id"));
+    let mut others = find_identified_brands(transaction, normalized_name)?;
+    others.retain(|id| Some(*id) != existing);
+    // With another identified brand under the same name, the brand known by name only
+    // could be either of them and is left alone.
+    let unidentified = match others.is_empty() {
+        true => find_unidentified_brand(transaction, normalized_name)?,
+        false => None,
+    };
+
+    match (existing, unidentified) {
+        (Some(id), Some(from_id)) => {
+            transaction.execute(
+                "UPDATE brand SET merged_into_id = $2 WHERE id = $1",
+                &[&from_id, &id],
+            )?;
+            transaction.execute(
+                "INSERT INTO brand_alias (brand_id, name, normalized_name)
+                SELECT $2, name, normalized_name FROM brand_alias WHERE brand_id = $1
+                ON CONFLICT DO NOTHING",
+                &[&from_id, &id],
+            )?;
+            transaction.execute("DELETE FROM brand_alias WHERE brand_id = $1", &[&from_id])?;
+            changes.push(BrandChange::Merged {
+                from_id,
+                into_id: id,
+                name: name.to_string(),
+                wikidata_id: wikidata_id.to_string(),
+            });
+            Ok(id)
+        }
+        (Some(id), None) => Ok(id),
+        (None, Some(id)) => {
+            transaction.execute(
+                "UPDATE brand SET wikidata_id = $2 WHERE id = $1",
+                &[&id, &wikidata_id],
+            )?;
+            changes.push(BrandChange::Identified {
+                brand_id: id,
+                name: name.to_string(),
+                wikidata_id: wikidata_id.to_string(),
+            });
+            Ok(id)
+        }
+        (None, None) => {
+            let row = transaction.query_one(
+                "INSERT INTO brand (name, normalized_name, wikidata_id)
+                VALUES ($1, $2, $3)
+                RETURNING id",
+                &[&name, &normalized_name, &wikidata_id],
+            )?;
+            let id = row.get("id");
+            if !others.is_empty() {
+                changes.push(BrandChange::Split {
+                    brand_id: id,
+                    name: name.to_string(),
+                    wikidata_id: wikidata_id.to_string(),
+                });
+            }
+            Ok(id)
+        }
+    }
+}
+
+fn resolve_named_brand(
+    transaction: &mut Transaction,
+    name: &str,
+    normalized_name: &str,
+) -> Result<i32, IngestionError> {
+    let identified = find_identified_brands(transaction, normalized_name)?;
+    if let [id] = identified[..] {
+        return Ok(id);
+    }
+    if let Some(id) = find_unidentified_brand(transaction, normalized_name)? {
+        return Ok(id);
+    }
+    let row = transaction.query_one(
+        "INSERT INTO brand (name, normalized_name) VALUES ($1, $2) RETURNING id",
+        &[&name, &normalized_name],
+    )?;
+    Ok(row.get("id"))
+}
+
+/// Points the POIs of the session's `poi` table at the brands others were merged into.
+pub fn apply_brand_merges(
+    client: &mut Client,
+    changes: &[BrandChange],
+) -> Result<u64, IngestionError> {
+    let mut transaction = client.transaction()?;
+    let mut count = 0;
+    for change in changes {
+        if let BrandChange::Merged {
+            from_id, into_id, ..
+        } = change
+        {
+            count += transaction.execute(
+                "UPDATE poi SET brand_id = $2 WHERE brand_id = $1",
+                &[from_id, into_id],
+            )?;
+        }
+    }
+    transaction.commit()?;
+    info!("{} POIs moved to the brands they were merged into", count);
+    Ok(count)
+}
+
+/// Makes sure the operators of `pois` exist and returns their ids by name.
+pub fn ingest_operators_into_db(
+    client: &mut Client,
+    pois: &[POI],
+) -> Result<BTreeMap<String, i32>, IngestionError> {
+    let query = "
+        INSERT INTO operator (name, wikidata_id)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE
+        SET wikidata_id = COALESCE(operator.wikidata_id, EXCLUDED.wikidata_id)
+        RETURNING id";
+    let mut operator_ids = BTreeMap::new();
+    for poi in pois {
+        let name = match operator_name(poi) {
+            Some(value) if !operator_ids.contains_key(value) => value,
+            _ => continue,
+        };
+        let row = client.query_one(query, &[&name, &poi.operator_wikidata_id])?;
+        operator_ids.insert(name.to_string(), row.get("id"));
+    }
+    Ok(operator_ids)
+}
+
+fn operator_name(poi: &POI) -> Option<&str> {
+    poi.operator
+        .as_deref()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+/// Rebuilds the brand to operators mapping from the POIs of the session's `poi` table.
+pub fn refresh_brand_operators(client: &mut Client) -> Result<u64, IngestionError> {
+    let query = "
+        INSERT INTO brand_operator (brand_id, operator_id, poi_count)
+        SELECT brand_id, operator_id, COUNT(1)
+        FROM poi
+        WHERE deleted_at IS NULL AND brand_id IS NOT NULL AND operator_id IS NOT NULL
+        GROUP BY brand_id, operator_id";
+    let count = client.execute(query, &[])?;
+    info!("{} brand and operator pairs recorded", count);
+    Ok(count)
+}
+
+/// Rebuilds the spider to brands mapping from the POIs of the session's `poi` table.
+pub fn refresh_spider_brands(client: &mut Client) -> Result<u64, IngestionError> {
+    let query = "
+        INSERT INTO spider_brand (spider_id, brand_id, poi_count)
+        SELECT spider_id, brand_id, COUNT(1)
+        FROM poi
+        WHERE deleted_at IS NULL AND brand_id IS NOT NULL
+        GROUP BY spider_id, brand_id";
+    let count = client.execute(query, &[])?;
+    info!("{} spider and brand pairs recorded", count);
+    Ok(count)
+}
+
+/// Records the start of an ingestion run and returns its id.
+///
+/// The `run` table is never staged, so failed runs stay visible next to the live data.
+pub fn start_run(
+    client: &mut Client,
+    run_info: &AtpRunInfo,
+    zip_size: Option<i64>,
+) -> Result<i32, IngestionError> {
+    let query = "
+        INSERT INTO public.run (atp_run_at, source_url, zip_size, rows_announced, spiders_announced)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id";
+    let row = client.query_one(
+        query,
+        &[
+            &run_info.updated_at,
+            &run_info.file_url,
+            &zip_size,
+            &run_info.rows,
+            &run_info.spiders,
+        ],
+    )?;
+    let id: i32 = row.get("id");
+    info!("started ingestion run {}", id);
     Ok(id)
 }
+
+/// Records what the run saw of each spider in the live `spider` table.
+///
+/// The table is kept even when the run fails, it is how broken spiders are spotted. The
+/// brands come from the live `spider_brand` table, so they are the ones of the last
+/// successful run.
+pub fn record_spiders(
+    client: &mut Client,
+    run_id: i32,
+    report: &RunReport,
+) -> Result<u64, IngestionError> {
+    let query = "
+        INSERT INTO public.spider (id, source_file, brand_ids, feature_count, features_dropped,
+            last_run_id, status, error)
+        VALUES ($1, $2,
+            ARRAY(SELECT brand_id FROM public.spider_brand WHERE spider_id = $1 ORDER BY poi_count DESC),
+            $3, $4, $5, $6, $7)
+        ON CONFLICT (id) DO UPDATE
+        SET source_file = EXCLUDED.source_file, brand_ids = EXCLUDED.brand_ids,
+            previous_feature_count = spider.feature_count,
+            feature_count = EXCLUDED.feature_count, features_dropped = EXCLUDED.features_dropped,
+            last_run_id = EXCLUDED.last_run_id, status = EXCLUDED.status, error = EXCLUDED.error,
+            updated_at = now()";
+    let mut transaction = client.transaction()?;
+    let mut count = 0;
+    for spider in &report.spiders {
+        count += transaction.execute(
+            query,
+            &[
+                &spider.spider,
+                &spider.file,
+                &(spider.features as i32),
+                &(spider.dropped as i32),
+                &run_id,
+                &spider.status.name(),
+                &spider.status.reason(),
+            ],
+        )?;
+    }
+    transaction.commit()?;
+    info!("{} spiders recorded", count);
+    Ok(count)
+}
+
+/// Stores the outcome of an ingestion run, `status` is either `succeeded` or `failed`.
+pub fn finish_run(
+    client: &mut Client,
+    run_id: i32,
+    stats: &RunStats,
+    report: Option<&RunReport>,
+    status: &str,
+) -> Result<(), IngestionError> {
+    let query = "
+        UPDATE public.run
+        SET rows_ingested = $2, files_ingested = $3, files_skipped = $4,
+            files_failed = $5, features_dropped = $6, report = $7,
+            finished_at = now(), status = $8
+        WHERE id = $1";
+    client.execute(
+        query,
+        &[
+            &run_id,
+            &stats.rows_ingested,
+            &stats.files_ingested,
+            &stats.files_skipped,
+            &stats.files_failed,
+            &stats.features_dropped,
+            &report.map(Json),
+            &status,
+        ],
+    )?;
+    info!("ingestion run {} finished with status {}", run_id, status);
+    Ok(())
+}
+
+/// Returns the latest ingestion runs, newest first.
+pub fn fetch_runs(client: &mut Client, limit: i64) -> Result<Vec<Run>, IngestionError> {
+    let query = "
+        SELECT id, atp_run_at, status, started_at, finished_at,
+            rows_announced, rows_ingested, files_ingested, files_skipped,
+            files_failed, features_dropped
+        FROM public.run
+        ORDER BY id DESC
+        LIMIT $1";
+    let runs = client
+        .query(query, &[&limit])?
+        .iter()
+        .map(|row| Run {
+            id: row.get("id"),
+            atp_run_at: row.get("atp_run_at"),
+            status: row.get("status"),
+            started_at: row.get("started_at"),
+            finished_at: row.get("finished_at"),
+            rows_announced: row.get("rows_announced"),
+            rows_ingested: row.get("rows_ingested"),
+            files_ingested: row.get("files_ingested"),
+            files_skipped: row.get("files_skipped"),
+            files_failed: row.get("files_failed"),
+            features_dropped: row.get("features_dropped"),
+        })
+        .collect();
+    Ok(runs)
+}
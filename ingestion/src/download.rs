@@ -1,17 +1,27 @@
-use std::fs;
-use std::io::Write;
-use std::{fs::File, path::Path, time::Duration};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::{path::Path, time::Duration};
 
+use chrono::{DateTime, Utc};
 use log::{debug, error, info, warn};
+use md5::{Digest, Md5};
 use regex::Regex;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use serde::{Deserialize, Serialize};
 
-pub fn get_file_url(url: &str) -> String {
+use crate::error::IngestionError;
+use crate::model::AtpRunInfo;
+
+pub fn get_run_info(url: &str) -> Result<AtpRunInfo, IngestionError> {
     debug!("getting the latest URL download link");
     debug!("attempting to request URL: {}", url);
 
     // Make the HTTP request
-    let response = reqwest::blocking::get(url)
-        .unwrap_or_else(|e| panic!("failed to connect to {}: {}", url, e));
+    let response = reqwest::blocking::get(url).map_err(|e| {
+        error!("failed to connect to {}: {}", url, e);
+        e
+    })?;
 
     // Check status code
     if !response.status().is_success() {
@@ -19,11 +29,14 @@ pub fn get_file_url(url: &str) -> String {
             "request returned non-success status code: {}",
             response.status()
         );
-        panic!("failed to get the latest URL download link!");
+        return Err(IngestionError::Download(format!(
+            "failed to get the latest URL download link: status code {}",
+            response.status()
+        )));
     }
 
     debug!("parsing response body from request");
-    let body = response.text().expect("failed to parse response body");
+    let body = response.text()?;
 
     debug!("looking for URL pattern in response body");
     // unwrap is ok because it's a hardcoded value
@@ -32,79 +45,366 @@ pub fn get_file_url(url: &str) -> String {
     // Find the URL in the response body
     match re.captures(&body) {
         Some(captures) => {
-            let extracted_url = captures.get(1).expect("capture group not found").as_str();
+            // unwrap is ok because the pattern has exactly one group
+            let extracted_url = captures.get(1).unwrap().as_str();
 
             info!("got the latest URL download link: {}", extracted_url);
-            extracted_url.to_string()
+            let run_info = AtpRunInfo {
+                file_url: extracted_url.to_string(),
+                rows: parse_count(&body, "rows"),
+                spiders: parse_count(&body, "spiders").and_then(|value| value.try_into().ok()),
+                updated_at: parse_updated_at(&body),
+                ..Default::default()
+            };
+            info!(
+                "latest run announces {:?} rows from {:?} spiders, updated {:?}",
+                run_info.rows, run_info.spiders, run_info.updated_at
+            );
+            Ok(run_info)
         }
         None => {
             error!("no URL pattern found in response body");
-            panic!("could not find the latest URL download link!");
+            Err(IngestionError::Download(String::from(
+                "could not find the latest URL download link",
+            )))
+        }
+    }
+}
+
+pub fn save_run_info(path: &Path, run_info: &AtpRunInfo) -> Result<(), IngestionError> {
+    debug!("writing the run metadata to {}", path.display());
+    fs::write(path, serde_json::to_string(run_info)?)?;
+    Ok(())
+}
+
+/// Reads back the metadata saved by `save_run_info`, if any.
+pub fn load_run_info(path: &Path) -> Option<AtpRunInfo> {
+    let content = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(value) => Some(value),
+        Err(why) => {
+            warn!("the run metadata at {} is broken: {}", path.display(), why);
+            None
         }
     }
 }
 
+// Parses counts like "7,878,700 rows" out of the embed page.
+fn parse_count(body: &str, unit: &str) -> Option<i64> {
+    // unwrap is ok because the pattern is built from hardcoded units
+    let re = Regex::new(&format!(r"([\d,]+)\s*{}", unit)).unwrap();
+    let captures = re.captures(body)?;
+    captures.get(1)?.as_str().replace(',', "").parse().ok()
+}
+
+fn parse_updated_at(body: &str) -> Option<DateTime<Utc>> {
+    // unwrap is ok because it's a hardcoded value
+    let re = Regex::new(r"updated\s+(\d{4}-\d{2}-\d{2}T[\d:.]+(?:Z|[+-]\d{2}:\d{2}))").unwrap();
+    let captures = re.captures(body)?;
+    DateTime::parse_from_rfc3339(captures.get(1)?.as_str())
+        .ok()
+        .map(|value| value.with_timezone(&Utc))
+}
+
+/// Tells whether `output_path` already holds the zip file of the run announced by `current`.
+///
+/// `previous` is the metadata saved after the last completed download.
+pub fn is_already_downloaded(
+    output_path: &Path,
+    previous: Option<&AtpRunInfo>,
+    current: &AtpRunInfo,
+) -> bool {
+    let previous = match previous {
+        Some(value) => value,
+        None => return false,
+    };
+    let size = match fs::metadata(output_path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return false,
+    };
+    previous.file_url == current.file_url
+        && previous.updated_at == current.updated_at
+        && previous.size == Some(size)
+}
+
+/// Where the `.part` file was downloaded from, saved next to it.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct PartSource {
+    url: String,
+    /// The ETag of the file, or else its Last-Modified date, sent back in `If-Range`.
+    validator: String,
+}
+
+/// Streams the zip file at `file_url` into `output_path`.
+///
+/// The body goes through a `.part` file that is resumed with a Range request when a
+/// previous download of the same URL was interrupted, and is only renamed to `output_path` once its
+/// size, and its MD5 when the ETag is one, have been verified. Returns the size and the
+/// ETag of the downloaded file.
 pub fn download_atp_data(
-    output_path: &str,
+    output_path: &Path,
     file_url: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(output_path);
-    if let Some(parent) = path.parent() {
+) -> Result<(u64, Option<String>), IngestionError> {
+    if let Some(parent) = output_path.parent() {
         debug!("creating directory at {}", parent.display());
         fs::create_dir_all(parent).map_err(|e| {
             error!("failed to create directory {}: {}", parent.display(), e);
             e
         })?;
     }
+    let part_path = output_path.with_extension("zip.part");
+    let part_source_path = output_path.with_extension("zip.part.source");
 
     debug!("building HTTP client");
+    // No total timeout, the file is several hundred MB and is streamed.
     let client = reqwest::blocking::Client::builder()
-        .timeout(Some(Duration::new(120, 0)))
+        .timeout(None)
+        .connect_timeout(Duration::new(30, 0))
         .build()
         .map_err(|e| {
             error!("failed to build HTTP client: {}", e);
             e
         })?;
 
+    let mut offset = fs::metadata(&part_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let mut request = client.get(file_url);
+    if offset > 0 {
+        // Without a validator the part file could be the head of another run.
+        match load_part_source(&part_source_path).filter(|source| source.url == file_url) {
+            Some(source) => {
+                info!("resuming the download of {} from byte {}", file_url, offset);
+                // The server only honours the range if the file did not change since.
+                request = request
+                    .header(RANGE, format!("bytes={}-", offset))
+                    .header(IF_RANGE, source.validator);
+            }
+            None => {
+                warn!(
+                    "the part file at {} doesn't come from {}, restarting the download",
+                    part_path.display(),
+                    file_url
+                );
+                fs::remove_file(&part_path)?;
+                let _ = fs::remove_file(&part_source_path);
+                offset = 0;
+            }
+        }
+    }
+
     debug!("downloading zip file from {}", file_url);
-    let resp = client.get(file_url).send().map_err(|e| {
+    let mut resp = request.send().map_err(|e| {
         error!("request to {} failed: {}", file_url, e);
         e
     })?;
 
-    if !resp.status().is_success() {
-        let status = resp.status();
+    let status = resp.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+        // The part file is only complete if the server reports the same total size.
+        let total = resp
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("bytes */"))
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        if total != Some(offset) {
+            warn!(
+                "the part file at {} has {} bytes but the server reports {:?}, restarting the download",
+                part_path.display(),
+                offset,
+                total
+            );
+            fs::remove_file(&part_path)?;
+            let _ = fs::remove_file(&part_source_path);
+            // Without the part file the request has no range, so this only recurses once.
+            return download_atp_data(output_path, file_url);
+        }
+        debug!(
+            "the part file at {} is already complete",
+            part_path.display()
+        );
+    } else if !status.is_success() {
         error!(
             "received non-success status code {} from {}",
             status, file_url
         );
-        return Err(format!("HTTP error: status code {}", status).into());
+        return Err(IngestionError::Download(format!(
+            "HTTP error: status code {}",
+            status
+        )));
     }
 
-    let bytes = resp.bytes().map_err(|e| {
-        error!("failed to read response body from {}: {}", file_url, e);
-        e
-    })?;
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let resumed = status == StatusCode::PARTIAL_CONTENT;
+    let expected_size = if resumed {
+        resp.headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit('/').next())
+            .and_then(|value| value.parse::<u64>().ok())
+    } else if status.is_success() {
+        resp.content_length()
+    } else {
+        Some(offset)
+    };
 
-    info!(
-        "successfully downloaded {} bytes from {}",
-        bytes.len(),
-        file_url
-    );
+    if status.is_success() {
+        let mut file = if resumed {
+            OpenOptions::new().append(true).open(&part_path)
+        } else {
+            if offset > 0 {
+                warn!(
+                    "the server restarted the download of {} from scratch",
+                    file_url
+                );
+            }
+            File::create(&part_path)
+        }
+        .map_err(|e| {
+            error!("failed to open file at {}: {}", part_path.display(), e);
+            e
+        })?;
+        let validator = etag.clone().or_else(|| {
+            resp.headers()
+                .get(LAST_MODIFIED)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        });
+        match validator {
+            Some(validator) => {
+                let source = PartSource {
+                    url: file_url.to_string(),
+                    validator,
+                };
+                fs::write(&part_source_path, serde_json::to_string(&source)?)?;
+            }
+            None => {
+                let _ = fs::remove_file(&part_source_path);
+            }
+        }
 
-    debug!("creating and writing to output file at {}", output_path);
-    let mut file = File::create(path).map_err(|e| {
-        error!("failed to create file at {}: {}", output_path, e);
-        e
-    })?;
+        let start = if resumed { offset } else { 0 };
+        let written = stream_to_file(&mut resp, &mut file, start, expected_size)?;
+        info!(
+            "successfully downloaded {} bytes from {}",
+            written, file_url
+        );
+    }
 
-    file.write_all(&bytes).map_err(|e| {
-        error!("failed to write data to {}: {}", output_path, e);
+    let size = fs::metadata(&part_path)?.len();
+    if let Some(expected) = expected_size.filter(|expected| *expected != size) {
+        error!(
+            "the downloaded file has {} bytes instead of {}",
+            size, expected
+        );
+        return Err(IngestionError::Download(format!(
+            "incomplete download: {} of {} bytes",
+            size, expected
+        )));
+    }
+    if let Some(expected) = etag.as_deref().and_then(md5_from_etag) {
+        let actual = file_md5(&part_path)?;
+        if actual != expected {
+            error!(
+                "the MD5 of the downloaded file is {} instead of {}",
+                actual, expected
+            );
+            fs::remove_file(&part_path)?;
+            return Err(IngestionError::Download(format!(
+                "checksum mismatch: {} instead of {}",
+                actual, expected
+            )));
+        }
+        debug!("the MD5 of the downloaded file matches the ETag");
+    }
+
+    fs::rename(&part_path, output_path).map_err(|e| {
+        error!(
+            "failed to move {} to {}: {}",
+            part_path.display(),
+            output_path.display(),
+            e
+        );
         e
     })?;
+    let _ = fs::remove_file(&part_source_path);
 
-    info!("successfully wrote file to {}", output_path);
-    Ok(())
+    info!("successfully wrote file to {}", output_path.display());
+    Ok((size, etag))
+}
+
+// Reads back the `PartSource` of a part file, a broken one is as good as none.
+fn load_part_source(path: &Path) -> Option<PartSource> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+// Copies the body in chunks and logs the progress every 10% (or every 50 MB).
+fn stream_to_file(
+    reader: &mut impl Read,
+    file: &mut File,
+    start: u64,
+    expected_size: Option<u64>,
+) -> Result<u64, IngestionError> {
+    const LOG_EVERY_BYTES: u64 = 50 * 1024 * 1024;
+    let mut buffer = vec![0; 1024 * 1024];
+    let mut written = start;
+    let mut last_logged = start;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])?;
+        written += read as u64;
+        let step = expected_size.map_or(LOG_EVERY_BYTES, |total| (total / 10).max(1));
+        if written - last_logged >= step {
+            last_logged = written;
+            match expected_size {
+                Some(total) => info!(
+                    "downloaded {} of {} MB ({}%)",
+                    written / 1024 / 1024,
+                    total / 1024 / 1024,
+                    written * 100 / total.max(1)
+                ),
+                None => info!("downloaded {} MB", written / 1024 / 1024),
+            }
+        }
+    }
+    file.flush()?;
+    Ok(written)
+}
+
+// Single part uploads on S3 compatible storages use the MD5 of the file as ETag. A weak
+// ETag doesn't promise the same bytes, so it is never read as one.
+fn md5_from_etag(etag: &str) -> Option<String> {
+    if etag.starts_with("W/") {
+        return None;
+    }
+    let value = etag.trim_matches('"');
+    if value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(value.to_lowercase())
+    } else {
+        None
+    }
+}
+
+fn file_md5(path: &Path) -> Result<String, IngestionError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
 #[cfg(test)]
@@ -112,7 +412,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_get_file_url_success() {
+    fn test_get_run_info_success() {
         let mock_body = r#"<!DOCTYPE html><html><body><a href="https://example.com/file.zip">Download GeoJSON</a>(575 MB)<br/><small>7,878,700 rows from3,694 spiders, updated 2025-03-17T14:47:33Z</small></body></html>"#;
         let mut server = mockito::Server::new();
         let url = server.url();
@@ -122,29 +422,343 @@ mod tests {
             .with_body(mock_body)
             .create();
 
-        let result = get_file_url(&url);
-        assert_eq!(result, "https://example.com/file.zip");
+        let result = get_run_info(&url).unwrap();
+        assert_eq!(result.file_url, "https://example.com/file.zip");
+        assert_eq!(result.rows, Some(7_878_700));
+        assert_eq!(result.spiders, Some(3_694));
+        assert_eq!(
+            result.updated_at,
+            Some(
+                DateTime::parse_from_rfc3339("2025-03-17T14:47:33Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_run_info() {
+        let dir = tempdir::TempDir::new("run_info").expect("Failed to create temp dir");
+        let path = dir.path().join("output.json");
+        let run_info = AtpRunInfo {
+            file_url: String::from("https://example.com/file.zip"),
+            rows: Some(10),
+            spiders: Some(2),
+            updated_at: None,
+            size: Some(42),
+            etag: Some(String::from("\"abc\"")),
+        };
+        save_run_info(&path, &run_info).unwrap();
+        assert_eq!(load_run_info(&path), Some(run_info));
+    }
+
+    fn write_part_source(output_path: &Path, url: &str, validator: &str) {
+        let source = PartSource {
+            url: url.to_string(),
+            validator: validator.to_string(),
+        };
+        fs::write(
+            output_path.with_extension("zip.part.source"),
+            serde_json::to_string(&source).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_download_atp_data_streams_to_file() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/output.zip")
+            .with_status(200)
+            .with_header("etag", "\"multipart-2\"")
+            .with_body("0123456789")
+            .create();
+
+        let (size, etag) =
+            download_atp_data(&output_path, &format!("{}/output.zip", server.url())).unwrap();
+        assert_eq!(size, 10);
+        assert_eq!(etag, Some(String::from("\"multipart-2\"")));
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "0123456789");
+        assert!(!output_path.with_extension("zip.part").exists());
+    }
+
+    #[test]
+    fn test_download_atp_data_resumes_part_file() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "01234").unwrap();
+        let mut server = mockito::Server::new();
+        let file_url = format!("{}/output.zip", server.url());
+        write_part_source(&output_path, &file_url, "\"v1\"");
+        let mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", "bytes=5-")
+            .match_header("if-range", "\"v1\"")
+            .with_status(206)
+            .with_header("etag", "\"v1\"")
+            .with_header("content-range", "bytes 5-9/10")
+            .with_body("56789")
+            .create();
+
+        let (size, _) = download_atp_data(&output_path, &file_url).unwrap();
+        mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "0123456789");
+    }
+
+    #[test]
+    fn test_download_atp_data_restarts_when_range_is_ignored() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "old").unwrap();
+        let mut server = mockito::Server::new();
+        let file_url = format!("{}/output.zip", server.url());
+        write_part_source(&output_path, &file_url, "\"v1\"");
+        let mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", "bytes=3-")
+            .with_status(200)
+            .with_body("0123456789")
+            .create();
+
+        let (size, _) = download_atp_data(&output_path, &file_url).unwrap();
+        mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "0123456789");
+    }
+
+    #[test]
+    fn test_download_atp_data_part_file_already_complete() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "0123456789").unwrap();
+        let mut server = mockito::Server::new();
+        let file_url = format!("{}/output.zip", server.url());
+        write_part_source(&output_path, &file_url, "Tue, 14 Jan 2025 10:00:00 GMT");
+        let mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", "bytes=10-")
+            .with_status(416)
+            .with_header("content-range", "bytes */10")
+            .create();
+
+        let (size, _) = download_atp_data(&output_path, &file_url).unwrap();
+        mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "0123456789");
+    }
+
+    #[test]
+    fn test_download_atp_data_restarts_when_part_file_is_too_long() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "0123456789ab").unwrap();
+        let mut server = mockito::Server::new();
+        let file_url = format!("{}/output.zip", server.url());
+        write_part_source(&output_path, &file_url, "\"v1\"");
+        let range_mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", "bytes=12-")
+            .with_status(416)
+            .with_header("content-range", "bytes */10")
+            .create();
+        let full_mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body("0123456789")
+            .create();
+
+        let (size, _) = download_atp_data(&output_path, &file_url).unwrap();
+        range_mock.assert();
+        full_mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "0123456789");
+        assert!(!output_path.with_extension("zip.part.source").exists());
+    }
+
+    #[test]
+    fn test_download_atp_data_restarts_part_file_without_source() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "01234").unwrap();
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_body("abcdefghij")
+            .create();
+
+        let (size, _) =
+            download_atp_data(&output_path, &format!("{}/output.zip", server.url())).unwrap();
+        mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "abcdefghij");
+    }
+
+    #[test]
+    fn test_download_atp_data_restarts_part_file_of_another_url() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        fs::write(output_path.with_extension("zip.part"), "01234").unwrap();
+        let mut server = mockito::Server::new();
+        write_part_source(
+            &output_path,
+            &format!("{}/runs/old/output.zip", server.url()),
+            "\"v1\"",
+        );
+        let mock = server
+            .mock("GET", "/output.zip")
+            .match_header("range", mockito::Matcher::Missing)
+            .with_status(200)
+            .with_header("etag", "\"v2\"")
+            .with_body("abcdefghij")
+            .create();
+
+        let (size, etag) =
+            download_atp_data(&output_path, &format!("{}/output.zip", server.url())).unwrap();
+        mock.assert();
+        assert_eq!(size, 10);
+        assert_eq!(etag, Some(String::from("\"v2\"")));
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "abcdefghij");
+    }
+
+    #[test]
+    fn test_download_atp_data_checksum_mismatch() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/output.zip")
+            .with_status(200)
+            .with_header("etag", "\"00000000000000000000000000000000\"")
+            .with_body("0123456789")
+            .create();
+
+        let result = download_atp_data(&output_path, &format!("{}/output.zip", server.url()));
+        assert!(result.is_err());
+        assert!(!output_path.exists());
+    }
+
+    #[test]
+    fn test_download_atp_data_checksum_match() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        let mut server = mockito::Server::new();
+        let _mock = server
+            .mock("GET", "/output.zip")
+            .with_status(200)
+            .with_header("etag", "\"781E5E245D69B566979B86E28D23F2C7\"")
+            .with_body("0123456789")
+            .create();
+
+        let result = download_atp_data(&output_path, &format!("{}/output.zip", server.url()));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_is_already_downloaded() {
+        let dir = tempdir::TempDir::new("download").expect("Failed to create temp dir");
+        let output_path = dir.path().join("output.zip");
+        let current = AtpRunInfo {
+            file_url: String::from("https://example.com/runs/1/output.zip"),
+            ..Default::default()
+        };
+        let previous = AtpRunInfo {
+            file_url: String::from("https://example.com/runs/1/output.zip"),
+            size: Some(10),
+            ..Default::default()
+        };
+        assert!(!is_already_downloaded(
+            &output_path,
+            Some(&previous),
+            &current
+        ));
+
+        fs::write(&output_path, "0123456789").unwrap();
+        assert!(is_already_downloaded(
+            &output_path,
+            Some(&previous),
+            &current
+        ));
+        assert!(!is_already_downloaded(&output_path, None, &current));
+
+        let newer = AtpRunInfo {
+            file_url: String::from("https://example.com/runs/2/output.zip"),
+            ..Default::default()
+        };
+        assert!(!is_already_downloaded(
+            &output_path,
+            Some(&previous),
+            &newer
+        ));
+
+        fs::write(&output_path, "01234").unwrap();
+        assert!(!is_already_downloaded(
+            &output_path,
+            Some(&previous),
+            &current
+        ));
+    }
+
+    #[test]
+    fn test_md5_from_etag() {
+        assert_eq!(
+            md5_from_etag("\"781E5E245D69B566979B86E28D23F2C7\""),
+            Some(String::from("781e5e245d69b566979b86e28d23f2c7"))
+        );
+        assert!(md5_from_etag("\"781e5e245d69b566979b86e28d23f2c7-12\"").is_none());
+        assert!(md5_from_etag("W/\"abc\"").is_none());
+        assert!(md5_from_etag("W/\"781E5E245D69B566979B86E28D23F2C7\"").is_none());
+    }
+
+    #[test]
+    fn test_load_run_info_missing() {
+        let dir = tempdir::TempDir::new("run_info").expect("Failed to create temp dir");
+        assert!(load_run_info(&dir.path().join("output.json")).is_none());
+    }
+
+    #[test]
+    fn test_get_run_info_without_metadata() {
+        let mock_body =
+            r#"<html><body><a href="https://example.com/file.zip">Download</a></body></html>"#;
+        let mut server = mockito::Server::new();
+        let url = server.url();
+        let _mock = server
+            .mock("GET", "/")
+            .with_status(200)
+            .with_body(mock_body)
+            .create();
+
+        let result = get_run_info(&url).unwrap();
+        assert_eq!(result.file_url, "https://example.com/file.zip");
+        assert!(result.rows.is_none());
+        assert!(result.spiders.is_none());
+        assert!(result.updated_at.is_none());
     }
 
     #[test]
-    #[should_panic(expected = "failed to connect to")]
-    fn test_get_file_url_connection_failure() {
+    fn test_get_run_info_connection_failure() {
         let url = "http://nonexistent.url";
-        get_file_url(url);
+        assert!(matches!(get_run_info(url), Err(IngestionError::Http(_))));
     }
 
     #[test]
-    #[should_panic(expected = "failed to get the latest URL download link")]
-    fn test_get_file_url_non_success_status() {
+    fn test_get_run_info_non_success_status() {
         let mut server = mockito::Server::new();
         let url = server.url();
         let _m = server.mock("GET", "/").with_status(404).create();
-        get_file_url(&url);
+        assert!(matches!(
+            get_run_info(&url),
+            Err(IngestionError::Download(message)) if message.contains("status code 404")
+        ));
     }
 
     #[test]
-    #[should_panic(expected = "could not find the latest URL download link!")]
-    fn test_get_file_url_no_url_in_body() {
+    fn test_get_run_info_no_url_in_body() {
         let mut server = mockito::Server::new();
         let url = server.url();
         let mock_body = r#"<html><body>No links here</body></html>"#;
@@ -154,12 +768,14 @@ mod tests {
             .with_body(mock_body)
             .create();
 
-        get_file_url(&url);
+        assert!(matches!(
+            get_run_info(&url),
+            Err(IngestionError::Download(_))
+        ));
     }
 
     #[test]
-    #[should_panic(expected = "could not find the latest URL download link!")]
-    fn test_get_file_url_invalid_body() {
+    fn test_get_run_info_invalid_body() {
         let mut server = mockito::Server::new();
         let url = server.url();
         let _m = server
@@ -167,6 +783,9 @@ mod tests {
             .with_status(200)
             .with_body("")
             .create();
-        get_file_url(&url);
+        assert!(matches!(
+            get_run_info(&url),
+            Err(IngestionError::Download(_))
+        ));
     }
 }
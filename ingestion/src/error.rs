@@ -0,0 +1,30 @@
+use thiserror::Error;
+
+/// Everything that can go wrong during an ingestion run.
+#[derive(Debug, Error)]
+pub enum IngestionError {
+    #[error("database error: {0}")]
+    Database(#[from] postgres::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("invalid feature: {0}")]
+    InvalidFeature(String),
+    #[error("download failed: {0}")]
+    Download(String),
+    #[error("invalid configuration: {0}")]
+    Config(String),
+    #[error("{failed} of {total} files failed, more than the threshold of {threshold}")]
+    TooManyFailures {
+        failed: usize,
+        total: usize,
+        threshold: f64,
+    },
+}
@@ -1,12 +1,16 @@
-use geojson::GeoJson;
 use log::error;
-use std::error::Error;
+use serde::Deserialize;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use std::fmt;
 use std::fs::{self, File};
+use std::io::{BufReader, Read};
 
 use std::path::Path;
 use walkdir::DirEntry;
 
-use crate::model::POI;
+use crate::error::IngestionError;
+use crate::model::{Feature, POI};
 
 pub fn is_file_empty(entry: &DirEntry) -> bool {
     let display = entry.path().display();
@@ -20,26 +24,169 @@ pub fn is_file_empty(entry: &DirEntry) -> bool {
     metadata.is_file() && metadata.len() == 0
 }
 
-pub fn read_geojson(entry: &DirEntry) -> Result<GeoJson, Box<dyn Error>> {
-    let display = entry.path().display();
+/// Streams the features of a GeoJSON `FeatureCollection` and calls `handle` for each.
+///
+/// Only one feature is held in memory at a time. A feature that doesn't match the
+/// model is handed over as an error, a reader that isn't a `FeatureCollection` fails the
+/// whole call. Returns the number of features read.
+pub fn read_features(
+    reader: &mut dyn Read,
+    handle: impl FnMut(Result<Feature, IngestionError>),
+) -> Result<usize, IngestionError> {
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let count = FeatureCollectionSeed { handle }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(count)
+}
 
-    let string_value = match fs::read_to_string(display.to_string()) {
-        Err(why) => return Err(why.into()),
-        Ok(value) => value,
+/// Parses a single GeoJSON feature into the model.
+///
+/// Next to the typed properties the feature keeps its whole `properties` object, the
+/// NUL characters Postgres refuses in JSONB are removed from its keys and values.
+pub fn parse_feature(raw: &str) -> Result<Feature, IngestionError> {
+    let mut feature: Feature = serde_json::from_str(raw)?;
+    let properties: RawProperties = serde_json::from_str(raw)?;
+    let tags = properties.properties.get();
+    // A NUL can only come from a `\u0000` escape, without one the raw object is kept as is.
+    feature.tags = match tags.contains("\\u0000") {
+        false => tags.to_string(),
+        true => {
+            let mut value: serde_json::Value = serde_json::from_str(tags)?;
+            strip_nul(&mut value);
+            value.to_string()
+        }
     };
-    match serde_json::from_str(string_value.as_str()) {
-        Err(why) => Err(why.into()),
-        Ok(value) => Ok(value),
+    let geometry_type = feature.geometry.as_ref().map(|value| value.r#type.as_str());
+    if let Some(value) = geometry_type.filter(|value| *value != "Point") {
+        return Err(IngestionError::InvalidFeature(format!(
+            "unsupported geometry type: {}",
+            value
+        )));
     }
+    Ok(feature)
 }
 
-pub fn write_to_csv(pois: Vec<POI>, output_file: &str) -> Result<(), Box<dyn Error>> {
-    let path = Path::new(output_file);
-    fs::create_dir_all(path.parent().unwrap())?;
-    let file = match File::create(output_file) {
-        Err(why) => panic!("error while creating the file {}: {}", output_file, why),
-        Ok(value) => value,
-    };
+fn strip_nul(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => text.retain(|character| character != '\0'),
+        serde_json::Value::Array(values) => values.iter_mut().for_each(strip_nul),
+        serde_json::Value::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    strip_nul(&mut value);
+                    (key.replace('\0', ""), value)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+#[derive(Deserialize)]
+struct RawProperties<'a> {
+    #[serde(borrow)]
+    properties: &'a RawValue,
+}
+
+struct FeatureCollectionSeed<F> {
+    handle: F,
+}
+
+impl<'de, F> DeserializeSeed<'de> for FeatureCollectionSeed<F>
+where
+    F: FnMut(Result<Feature, IngestionError>),
+{
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for FeatureCollectionSeed<F>
+where
+    F: FnMut(Result<Feature, IngestionError>),
+{
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a GeoJSON FeatureCollection")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<usize, A::Error> {
+        let mut count = None;
+        let mut is_collection = false;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" => {
+                    let value = map.next_value::<String>()?;
+                    if value != "FeatureCollection" {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Str(&value),
+                            &"FeatureCollection",
+                        ));
+                    }
+                    is_collection = true;
+                }
+                "features" => {
+                    count = Some(map.next_value_seed(FeaturesSeed {
+                        handle: &mut self.handle,
+                    })?);
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        if !is_collection {
+            return Err(de::Error::missing_field("type"));
+        }
+        count.ok_or_else(|| de::Error::missing_field("features"))
+    }
+}
+
+struct FeaturesSeed<'a, F> {
+    handle: &'a mut F,
+}
+
+impl<'de, F> DeserializeSeed<'de> for FeaturesSeed<'_, F>
+where
+    F: FnMut(Result<Feature, IngestionError>),
+{
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for FeaturesSeed<'_, F>
+where
+    F: FnMut(Result<Feature, IngestionError>),
+{
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of GeoJSON features")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+        let mut count = 0;
+        // Each feature is kept as raw JSON first, so a broken one doesn't fail the file.
+        while let Some(raw) = seq.next_element::<Box<RawValue>>()? {
+            count += 1;
+            (self.handle)(parse_feature(raw.get()));
+        }
+        Ok(count)
+    }
+}
+
+pub fn write_to_csv(pois: Vec<POI>, output_file: &str) -> Result<(), IngestionError> {
+    if let Some(parent) = Path::new(output_file).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = File::create(output_file)?;
     let mut wtr = csv::Writer::from_writer(file);
     for poi in pois {
         wtr.serialize(poi)?;
@@ -84,6 +231,24 @@ mod tests {
         assert!(!is_file_empty(&entry));
     }
 
+    type FileResult = (Result<usize, IngestionError>, Vec<bool>);
+
+    // Reads every file of the directory and returns the file level result together
+    // with the result of each feature.
+    fn read_directory(path: &Path) -> Vec<FileResult> {
+        let mut results = vec![];
+        for entry in WalkDir::new(path).into_iter().filter_map(Result::ok) {
+            if entry.path().is_file() {
+                let mut features = vec![];
+                let result = read_features(&mut File::open(entry.path()).unwrap(), |feature| {
+                    features.push(feature.is_ok())
+                });
+                results.push((result, features));
+            }
+        }
+        results
+    }
+
     #[test]
     fn test_read_geojson_valid() {
         let temp_dir = TempDir::new("geojson_test").expect("Failed to create temp dir");
@@ -94,11 +259,14 @@ mod tests {
             "features": [
                 {
                     "type": "Feature",
+                    "id": "uuid",
                     "geometry": {
                         "type": "Point",
                         "coordinates": [102.0, 0.5]
                     },
                     "properties": {
+                        "@spider": "spider_1",
+                        "@source_uri": "http://example.com",
                         "name": "Sample Point"
                     }
                 }
@@ -109,14 +277,9 @@ mod tests {
         file.write_all(geojson_content.as_bytes())
             .expect("Failed to write to test file");
 
-        for entry in WalkDir::new(temp_dir.path())
-            .into_iter()
-            .filter_map(Result::ok)
-        {
-            if entry.path().is_file() {
-                let result = read_geojson(&entry);
-                assert!(result.is_ok(), "Expected Ok, got Err: {:?}", result);
-            }
+        for (result, features) in read_directory(temp_dir.path()) {
+            assert!(result.is_ok(), "Expected Ok, got Err: {:?}", result);
+            assert_eq!(features, vec![true]);
         }
     }
 
@@ -130,13 +293,25 @@ mod tests {
             "features": [
                 {
                     "type": "Feature",
+                    "id": "uuid",
                     "geometry": {
                         "type": "INVALID_TYPE",
                         "coordinates": [102.0, 0.5]
                     },
                     "properties": {
+                        "@spider": "spider_1",
+                        "@source_uri": "http://example.com",
                         "name": "Sample Point"
                     }
+                },
+                {
+                    "type": "Feature",
+                    "id": "uuid2",
+                    "geometry": null,
+                    "properties": {
+                        "@spider": "spider_1",
+                        "@source_uri": "http://example.com"
+                    }
                 }
             ]
         }"#;
@@ -145,14 +320,26 @@ mod tests {
         file.write_all(invalid_geojson_content.as_bytes())
             .expect("Failed to write to test file");
 
-        for entry in WalkDir::new(temp_dir.path())
-            .into_iter()
-            .filter_map(Result::ok)
-        {
-            if entry.path().is_file() {
-                let result = read_geojson(&entry);
-                assert!(result.is_err(), "Expected Err, got Ok: {:?}", result);
-            }
+        // The broken feature is reported but doesn't stop the next ones.
+        for (result, features) in read_directory(temp_dir.path()) {
+            assert_eq!(result.unwrap(), 2);
+            assert_eq!(features, vec![false, true]);
+        }
+    }
+
+    #[test]
+    fn test_read_geojson_not_a_feature_collection() {
+        let temp_dir = TempDir::new("geojson_test").expect("Failed to create temp dir");
+        let file_path = temp_dir.path().join("feature.geojson");
+
+        let content = r#"{"type": "Feature", "features": []}"#;
+
+        let mut file = File::create(&file_path).expect("Failed to create test file");
+        file.write_all(content.as_bytes())
+            .expect("Failed to write to test file");
+
+        for (result, _) in read_directory(temp_dir.path()) {
+            assert!(result.is_err(), "Expected Err, got Ok: {:?}", result);
         }
     }
 
@@ -167,14 +354,51 @@ mod tests {
         file.write_all(non_json_content.as_bytes())
             .expect("Failed to write to test file");
 
-        for entry in WalkDir::new(temp_dir.path())
-            .into_iter()
-            .filter_map(Result::ok)
-        {
-            if entry.path().is_file() {
-                let result = read_geojson(&entry);
-                assert!(result.is_err(), "Expected Err, got Ok: {:?}", result);
-            }
+        for (result, _) in read_directory(temp_dir.path()) {
+            assert!(result.is_err(), "Expected Err, got Ok: {:?}", result);
         }
     }
+
+    #[test]
+    fn test_parse_feature_keeps_tags() {
+        let raw = r#"{
+            "type": "Feature",
+            "id": "uuid",
+            "geometry": {"type": "Point", "coordinates": [102.0, 0.5]},
+            "properties": {
+                "@spider": "spider_1",
+                "@source_uri": "http://example.com",
+                "shop": "supermarket",
+                "fuel:diesel": "yes",
+                "name": "Sample\u0000 Point"
+            }
+        }"#;
+
+        let feature = parse_feature(raw).unwrap();
+        let tags: serde_json::Value = serde_json::from_str(&feature.tags).unwrap();
+        assert_eq!(tags["shop"], "supermarket");
+        assert_eq!(tags["fuel:diesel"], "yes");
+        assert_eq!(tags["@spider"], "spider_1");
+        assert_eq!(tags["name"], "Sample Point");
+    }
+
+    #[test]
+    fn test_parse_feature_keeps_escaped_backslashes() {
+        let raw = r#"{
+            "type": "Feature",
+            "id": "uuid",
+            "geometry": {"type": "Point", "coordinates": [102.0, 0.5]},
+            "properties": {
+                "@spider": "spider_1",
+                "@source_uri": "http://example.com",
+                "note": "C:\\u0000",
+                "brand\u0000": ["A\u0000B"]
+            }
+        }"#;
+
+        let feature = parse_feature(raw).unwrap();
+        let tags: serde_json::Value = serde_json::from_str(&feature.tags).unwrap();
+        assert_eq!(tags["note"], "C:\\u0000");
+        assert_eq!(tags["brand"][0], "AB");
+    }
 }
@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use country_boundaries::{BOUNDARIES_ODBL_360X180, CountryBoundaries, LatLon};
+use geo::{BoundingRect, Contains, Geometry, MultiPolygon, Point};
+use geojson::GeoJson;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use rstar::{AABB, RTree, RTreeObject};
+use serde::{Deserialize, Serialize};
+
+use crate::error::IngestionError;
+
+lazy_static! {
+    static ref BOUNDARIES: CountryBoundaries =
+        CountryBoundaries::from_reader(BOUNDARIES_ODBL_360X180)
+            .expect("error while initializing the country boundaries");
+}
+
+/// Where a point is, as far as a geocoder can tell.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Location {
+    /// ISO 3166 ids, the smallest area first and the country last, e.g. `["US-TX", "US"]`.
+    pub boundary_ids: Vec<String>,
+    /// The areas of the local boundary files, by level, e.g. `municipality`.
+    pub admin_areas: BTreeMap<String, AdminArea>,
+}
+
+/// An area of a local boundary file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminArea {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Finds the areas a point falls in.
+pub trait Geocoder: Send + Sync {
+    fn locate(&self, point: &Point) -> Location;
+}
+
+/// The countries and subdivisions baked into `country_boundaries`, coarse near borders.
+pub struct CountryBoundariesGeocoder;
+
+impl Geocoder for CountryBoundariesGeocoder {
+    fn locate(&self, point: &Point) -> Location {
+        let latlong = match LatLon::new(point.y(), point.x()) {
+            Err(why) => {
+                warn!("invalid coordinates {:?}: {}", point, why);
+                return Location::default();
+            }
+            Ok(value) => value,
+        };
+        Location {
+            boundary_ids: BOUNDARIES
+                .ids(latlong)
+                .into_iter()
+                .map(|value| value.to_string())
+                .collect(),
+            admin_areas: BTreeMap::new(),
+        }
+    }
+}
+
+/// A local GeoJSON file of polygons, e.g. municipalities or postal areas, given on the
+/// command line as `level=path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundaryFile {
+    pub level: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for BoundaryFile {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some((level, path)) if !level.trim().is_empty() && !path.trim().is_empty() => {
+                Ok(BoundaryFile {
+                    level: level.trim().to_string(),
+                    path: PathBuf::from(path.trim()),
+                })
+            }
+            _ => Err(format!("expected `level=path`, got `{}`", value)),
+        }
+    }
+}
+
+/// A polygon of a boundary file, indexed by its bounding box.
+struct Area {
+    area: AdminArea,
+    polygon: MultiPolygon,
+    envelope: AABB<[f64; 2]>,
+}
+
+impl RTreeObject for Area {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+/// Adds the areas of local boundary files to the countries of another geocoder.
+pub struct BoundaryFileGeocoder<G: Geocoder> {
+    countries: G,
+    levels: Vec<(String, RTree<Area>)>,
+}
+
+impl<G: Geocoder> BoundaryFileGeocoder<G> {
+    /// Loads the polygons of each file, features without an id or a polygon are skipped.
+    pub fn load(countries: G, files: &[BoundaryFile]) -> Result<Self, IngestionError> {
+        let mut levels = vec![];
+        for file in files {
+            let reader = BufReader::new(File::open(&file.path)?);
+            let areas = read_areas(serde_json::from_reader(reader)?).map_err(|why| {
+                IngestionError::Config(format!("{}: {}", file.path.display(), why))
+            })?;
+            info!(
+                "loaded {} {} boundaries from {}",
+                areas.len(),
+                file.level,
+                file.path.display()
+            );
+            levels.push((file.level.clone(), RTree::bulk_load(areas)));
+        }
+        Ok(BoundaryFileGeocoder { countries, levels })
+    }
+}
+
+impl<G: Geocoder> Geocoder for BoundaryFileGeocoder<G> {
+    fn locate(&self, point: &Point) -> Location {
+        let mut location = self.countries.locate(point);
+        let envelope = AABB::from_point([point.x(), point.y()]);
+        for (level, areas) in &self.levels {
+            let found = areas
+                .locate_in_envelope_intersecting(&envelope)
+                .find(|area| area.polygon.contains(point));
+            if let Some(area) = found {
+                location
+                    .admin_areas
+                    .insert(level.clone(), area.area.clone());
+            }
+        }
+        location
+    }
+}
+
+/// The default geocoder, with the boundary files on top when there are any.
+pub fn build_geocoder(files: &[BoundaryFile]) -> Result<Box<dyn Geocoder>, IngestionError> {
+    match files.is_empty() {
+        true => Ok(Box::new(CountryBoundariesGeocoder)),
+        false => Ok(Box::new(BoundaryFileGeocoder::load(
+            CountryBoundariesGeocoder,
+            files,
+        )?)),
+    }
+}
+
+/// The id is the one of the feature, or else its `id`, `ref` or `code` property.
+fn read_areas(geojson: GeoJson) -> Result<Vec<Area>, String> {
+    let collection = match geojson {
+        GeoJson::FeatureCollection(value) => value,
+        _ => return Err(String::from("expected a FeatureCollection")),
+    };
+    let mut areas = vec![];
+    for feature in collection.features {
+        let property = |key: &str| {
+            feature
+                .property(key)
+                .and_then(|value| match value {
+                    serde_json::Value::String(text) => Some(text.trim().to_string()),
+                    serde_json::Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                })
+                .filter(|value| !value.is_empty())
+        };
+        let id = match &feature.id {
+            Some(geojson::feature::Id::String(value)) => Some(value.clone()),
+            Some(geojson::feature::Id::Number(value)) => Some(value.to_string()),
+            None => None,
+        }
+        .or_else(|| property("id"))
+        .or_else(|| property("ref"))
+        .or_else(|| property("code"));
+        let name = property("name");
+        let geometry = feature
+            .geometry
+            .as_ref()
+            .and_then(|value| Geometry::<f64>::try_from(value).ok());
+        let polygon = match geometry {
+            Some(Geometry::Polygon(value)) => MultiPolygon::new(vec![value]),
+            Some(Geometry::MultiPolygon(value)) => value,
+            _ => continue,
+        };
+        let (id, rect) = match (id, polygon.bounding_rect()) {
+            (Some(id), Some(rect)) => (id, rect),
+            _ => continue,
+        };
+        areas.push(Area {
+            area: AdminArea { id, name },
+            envelope: AABB::from_corners(
+                [rect.min().x, rect.min().y],
+                [rect.max().x, rect.max().y],
+            ),
+            polygon,
+        });
+    }
+    Ok(areas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn boundary_ids(x: f64, y: f64) -> Vec<String> {
+        CountryBoundariesGeocoder
+            .locate(&Point::new(x, y))
+            .boundary_ids
+    }
+
+    #[test]
+    fn test_country_boundaries_fr() {
+        assert_eq!(
+            boundary_ids(2.3276581, 48.8805374).pop(),
+            Some(String::from("FR"))
+        );
+    }
+
+    #[test]
+    fn test_country_boundaries_en() {
+        assert_eq!(
+            boundary_ids(-0.14405508452768728, 51.4893335).pop(),
+            Some(String::from("GB"))
+        );
+    }
+
+    #[test]
+    fn test_country_boundaries_us() {
+        assert_eq!(
+            boundary_ids(-74.0060152, 40.7127281).pop(),
+            Some(String::from("US"))
+        );
+    }
+
+    #[test]
+    fn test_country_boundaries_water() {
+        assert_eq!(boundary_ids(3.864293, 54.375721).pop(), None);
+    }
+
+    #[test]
+    fn test_country_boundaries_invalid_coordinates() {
+        assert!(boundary_ids(200.0, 100.0).is_empty());
+    }
+
+    #[test]
+    fn test_boundary_file_from_str() {
+        assert_eq!(
+            "municipality=/data/communes.geojson".parse::<BoundaryFile>(),
+            Ok(BoundaryFile {
+                level: String::from("municipality"),
+                path: PathBuf::from("/data/communes.geojson"),
+            })
+        );
+        assert!("/data/communes.geojson".parse::<BoundaryFile>().is_err());
+        assert!("=/data/communes.geojson".parse::<BoundaryFile>().is_err());
+    }
+
+    #[test]
+    fn test_boundary_file_geocoder() {
+        let dir = TempDir::new("boundaries").unwrap();
+        let path = dir.path().join("municipalities.geojson");
+        let geojson = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "id": "75056",
+                    "properties": { "name": "Paris" },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[2.2, 48.8], [2.5, 48.8], [2.5, 48.9], [2.2, 48.9], [2.2, 48.8]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "code": 92012 },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[2.2, 48.7], [2.3, 48.7], [2.3, 48.8], [2.2, 48.8], [2.2, 48.7]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "ref": "T1" },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Without id" },
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[[3.0, 3.0], [4.0, 3.0], [4.0, 4.0], [3.0, 3.0]]]
+                    }
+                }
+            ]
+        });
+        File::create(&path)
+            .unwrap()
+            .write_all(geojson.to_string().as_bytes())
+            .unwrap();
+
+        let files = vec![BoundaryFile {
+            level: String::from("municipality"),
+            path,
+        }];
+        let geocoder = BoundaryFileGeocoder::load(CountryBoundariesGeocoder, &files).unwrap();
+        assert_eq!(geocoder.levels[0].1.size(), 3);
+
+        let location = geocoder.locate(&Point::new(2.3276581, 48.8805374));
+        assert_eq!(location.boundary_ids.last(), Some(&String::from("FR")));
+        assert_eq!(
+            location.admin_areas.get("municipality"),
+            Some(&AdminArea {
+                id: String::from("75056"),
+                name: Some(String::from("Paris")),
+            })
+        );
+        let location = geocoder.locate(&Point::new(2.25, 48.75));
+        assert_eq!(location.admin_areas["municipality"].id, "92012");
+        assert_eq!(location.admin_areas["municipality"].name, None);
+        assert_eq!(
+            geocoder.locate(&Point::new(0.9, 0.1)).admin_areas["municipality"].id,
+            "T1"
+        );
+        // In the bounding box of the triangle but outside of it.
+        let location = geocoder.locate(&Point::new(0.1, 0.9));
+        assert!(location.admin_areas.is_empty());
+    }
+
+    #[test]
+    fn test_boundary_file_not_a_collection() {
+        let dir = TempDir::new("boundaries").unwrap();
+        let path = dir.path().join("point.geojson");
+        File::create(&path)
+            .unwrap()
+            .write_all(br#"{"type": "Point", "coordinates": [0.0, 0.0]}"#)
+            .unwrap();
+        let files = vec![BoundaryFile {
+            level: String::from("postal_area"),
+            path,
+        }];
+        assert!(BoundaryFileGeocoder::load(CountryBoundariesGeocoder, &files).is_err());
+    }
+}
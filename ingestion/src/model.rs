@@ -1,8 +1,14 @@
 extern crate geo;
 
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
 use geo::geometry::Point;
 use serde::{Deserialize, Serialize};
 
+use crate::geocoder::AdminArea;
+use crate::opening_hours::WeeklySchedule;
+
 // https://github.com/alltheplaces/alltheplaces/blob/master/DATA_FORMAT.md
 #[derive(Deserialize, Debug)]
 pub struct Properties {
@@ -49,6 +55,13 @@ pub struct Properties {
     pub located_in_wikidata_id: Option<String>,
     pub nsi_id: Option<String>,
     pub end_date: Option<String>,
+    pub amenity: Option<String>,
+    pub shop: Option<String>,
+    pub tourism: Option<String>,
+    pub leisure: Option<String>,
+    pub healthcare: Option<String>,
+    pub office: Option<String>,
+    pub craft: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -60,38 +73,203 @@ pub struct Geometry {
 #[derive(Deserialize, Debug)]
 pub struct Feature {
     pub r#type: String,
+    /// Empty when the feature has none, `ref` is used instead.
+    #[serde(default)]
     pub id: String,
     pub properties: Properties,
     pub geometry: Option<Geometry>,
+    /// The original `properties` object as JSON, filled by `files::parse_feature`.
+    #[serde(skip)]
+    pub tags: String,
 }
 
 #[derive(Serialize, Debug, Deserialize)]
 pub struct POI {
     pub spider_id: String,
+    pub feature_id: String,
     pub poi_name: Option<String>,
+    /// The normalised store page.
     pub website: Option<String>,
+    pub website_host: Option<String>,
+    pub website_source: Option<WebsiteSource>,
     pub opening_hours: Option<String>,
+    #[serde(skip_deserializing)]
+    pub opening_schedule: Option<WeeklySchedule>,
+    /// Why `opening_hours` couldn't be parsed into `opening_schedule`.
+    pub opening_hours_error: Option<String>,
+    /// IANA time zone of the POI, the opening hours are in its local time.
+    pub timezone: Option<String>,
+    /// The raw phone value, as scraped.
     pub phone: Option<String>,
+    /// The valid numbers of `phone` in E.164 format.
+    pub phones: Vec<String>,
+    /// The parts of `phone` that aren't valid numbers.
+    pub invalid_phones: Vec<String>,
     pub point: Option<Point>,
     pub city: Option<String>,
     pub zipcode: Option<String>,
     pub house_number: Option<String>,
     pub street_address: Option<String>,
     pub country: Option<String>,
-    pub country_code: String,
+    /// NULL when the point isn't in any country and `addr:country` isn't a country code.
+    pub country_code: Option<String>,
+    pub geocode_status: GeocodeStatus,
+    /// What looks wrong with `point`, sorted.
+    pub coordinate_flags: Vec<CoordinateFlag>,
+    /// ISO 3166-2 code of the subdivision the point falls in, e.g. `US-TX`.
+    pub subdivision_code: Option<String>,
+    /// The areas of the local boundary files the point falls in, by level.
+    pub admin_areas: BTreeMap<String, AdminArea>,
     pub state: Option<String>,
     pub full_address: Option<String>,
     pub street_name: Option<String>,
+    /// The address fields filled from the other ones rather than scraped.
+    pub address_derived: Vec<String>,
+    /// Whether `zipcode` has the format of the country, `None` when either is unknown.
+    pub postcode_valid: Option<bool>,
+    pub poi_ref: Option<String>,
+    pub branch: Option<String>,
+    pub email: Option<String>,
+    pub contact_twitter: Option<String>,
+    pub contact_facebook: Option<String>,
+    pub image: Option<String>,
+    pub located_in: Option<String>,
+    pub located_in_wikidata_id: Option<String>,
+    pub nsi_id: Option<String>,
+    pub end_date: Option<String>,
+    pub operator: Option<String>,
+    pub operator_wikidata_id: Option<String>,
+    pub tags: String,
+    pub category: Option<Category>,
+    pub brand: Option<Brand>,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
+/// Where the website of a POI comes from.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebsiteSource {
+    /// The `website` property, the page of the store itself.
+    Website,
+    /// The `@source_uri` property, the page the spider scraped, used when there's no `website`.
+    SourceUri,
+}
+
+impl WebsiteSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebsiteSource::Website => "website",
+            WebsiteSource::SourceUri => "source_uri",
+        }
+    }
+}
+
+/// Where the country of a POI comes from.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GeocodeStatus {
+    /// The point falls into a country.
+    Geocoded,
+    /// The point isn't in any country, e.g. offshore, the `addr:country` code is used.
+    AddressCountry,
+    /// Neither the point nor the address tell the country.
+    NotGeocoded,
+}
+
+impl GeocodeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeocodeStatus::Geocoded => "geocoded",
+            GeocodeStatus::AddressCountry => "address_country",
+            GeocodeStatus::NotGeocoded => "not_geocoded",
+        }
+    }
+}
+
+/// Something suspicious about the coordinates of a POI, which is still ingested.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum CoordinateFlag {
+    /// The latitude or the longitude is out of its range.
+    OutOfRange,
+    /// The point is at `(0, 0)`, a default value rather than a location.
+    NullIsland,
+    /// The latitude and the longitude are most likely the wrong way round.
+    Swapped,
+    /// The point is in another country than the `addr:country` one.
+    CountryMismatch,
+    /// Another POI of the same spider has the exact same coordinates.
+    Duplicate,
+}
+
+impl CoordinateFlag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoordinateFlag::OutOfRange => "out_of_range",
+            CoordinateFlag::NullIsland => "null_island",
+            CoordinateFlag::Swapped => "swapped",
+            CoordinateFlag::CountryMismatch => "country_mismatch",
+            CoordinateFlag::Duplicate => "duplicate",
+        }
+    }
+}
+
+/// A parsed website of a POI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Website {
+    pub url: String,
+    pub host: String,
+    pub source: WebsiteSource,
+}
+
+/// The primary OSM feature tag of a POI, e.g. `amenity=pharmacy`.
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Category {
+    pub key: String,
+    pub value: String,
+}
+
+/// A brand is identified by its Wikidata id when it has one, by its name otherwise.
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Brand {
     pub name: String,
     pub wikidata_id: Option<String>,
 }
 
-#[derive(Serialize, Debug, Deserialize)]
-pub struct BrandWithPOIs {
-    pub brand: Brand,
-    pub pois: Vec<POI>,
+/// What `info_embed.html` announces about the latest ATP run, completed with the size
+/// and ETag of the zip file once it is downloaded.
+#[derive(Serialize, Debug, Deserialize, PartialEq, Default)]
+pub struct AtpRunInfo {
+    pub file_url: String,
+    pub rows: Option<i64>,
+    pub spiders: Option<i32>,
+    pub updated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub etag: Option<String>,
+}
+
+/// What an ingestion run actually wrote, stored on its `run` row.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    pub rows_ingested: i64,
+    pub files_ingested: i32,
+    pub files_skipped: i32,
+    pub files_failed: i32,
+    pub features_dropped: i64,
+}
+
+#[derive(Debug)]
+pub struct Run {
+    pub id: i32,
+    pub atp_run_at: Option<DateTime<Utc>>,
+    pub status: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub rows_announced: Option<i64>,
+    pub rows_ingested: i64,
+    pub files_ingested: i32,
+    pub files_skipped: i32,
+    pub files_failed: i32,
+    pub features_dropped: i64,
 }
@@ -0,0 +1,441 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use thiserror::Error;
+
+/// Minutes in a day.
+const DAY: u16 = 1440;
+
+const WEEKDAYS: [&str; 7] = ["mo", "tu", "we", "th", "fr", "sa", "su"];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Why an `opening_hours` value couldn't be turned into a weekly schedule.
+#[derive(Debug, Error, PartialEq)]
+#[error("{0}")]
+pub struct OpeningHoursError(String);
+
+/// The opening times of each day of the week, Monday first, in minutes since midnight.
+///
+/// Times spilling over midnight, like `Fr 22:00-02:00`, are split over both days.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeeklySchedule {
+    days: [Vec<(u16, u16)>; 7],
+}
+
+/// What a single rule of the value says about the days it selects.
+enum Times {
+    Open(Vec<(u16, u16)>),
+    Closed,
+}
+
+/// Parses the subset of the OSM `opening_hours` syntax that describes a regular week:
+/// weekday selectors (`Mo-Fr`, `Sa,Su`), time spans (`08:00-12:00,14:00-18:00`), `off`
+/// and `24/7`. Rules separated by `;` replace the times of the days they select, rules
+/// separated by `,` add to them.
+///
+/// Everything a weekly schedule can't hold is rejected: month and date selectors, week
+/// numbers, variable times like `sunrise`, fallback rules and public or school holidays,
+/// since a POI closed on `PH` would otherwise show as open on those days.
+pub fn parse_opening_hours(value: &str) -> Result<WeeklySchedule, OpeningHoursError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(OpeningHoursError(String::from("the value is empty")));
+    }
+    if let Some(reason) = unsupported_syntax(value) {
+        return Err(OpeningHoursError(format!("{} are not supported", reason)));
+    }
+
+    // Times may end after midnight until every rule is applied.
+    let mut days: [Vec<(u16, u16)>; 7] = Default::default();
+    for rule in value
+        .split(';')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+    {
+        for (index, part) in split_additional_rules(rule).into_iter().enumerate() {
+            let (selected, times) = parse_rule(&part)?;
+            for (day, spans) in days.iter_mut().enumerate() {
+                if !selected[day] {
+                    continue;
+                }
+                if index == 0 {
+                    spans.clear();
+                }
+                if let Times::Open(times) = &times {
+                    spans.extend(times);
+                }
+            }
+        }
+    }
+
+    let mut schedule = WeeklySchedule::default();
+    for (day, spans) in days.iter().enumerate() {
+        for &(start, end) in spans {
+            match end > DAY {
+                true => {
+                    schedule.days[day].push((start, DAY));
+                    schedule.days[(day + 1) % 7].push((0, end - DAY));
+                }
+                false => schedule.days[day].push((start, end)),
+            }
+        }
+    }
+    for spans in &mut schedule.days {
+        merge_spans(spans);
+    }
+    Ok(schedule)
+}
+
+/// What the value uses that a weekly schedule can't hold, if anything. Comments in
+/// quotes are left out.
+fn unsupported_syntax(value: &str) -> Option<&'static str> {
+    if value.contains("||") {
+        return Some("fallback rules");
+    }
+    let unquoted: String = value.split('"').step_by(2).collect();
+    let words: Vec<String> = unquoted
+        .split(|character: char| !character.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let has = |candidates: &[&str]| words.iter().any(|word| candidates.contains(&word.as_str()));
+    // Years are the only numbers with four digits.
+    let has_year = unquoted
+        .split(|character: char| !character.is_ascii_digit())
+        .any(|number| number.len() == 4);
+    if has(&MONTHS) || has(&["easter"]) || has_year {
+        return Some("month and date selectors");
+    }
+    if has(&["week"]) {
+        return Some("week selectors");
+    }
+    if has(&["sunrise", "sunset", "dawn", "dusk"]) {
+        return Some("variable times");
+    }
+    if has(&["ph", "sh"]) {
+        return Some("public and school holiday rules");
+    }
+    None
+}
+
+/// Splits `Mo-Fr 08:00-12:00, Sa 09:00-12:00` into its rules, without breaking the
+/// weekday list of `Mo,We 08:00-12:00` or the time spans of `08:00-12:00,14:00-18:00`.
+fn split_additional_rules(rule: &str) -> Vec<String> {
+    let mut rules: Vec<String> = vec![];
+    for part in rule.split(',') {
+        let starts_with_selector = part
+            .trim_start()
+            .chars()
+            .next()
+            .is_some_and(|character| character.is_alphabetic());
+        let has_times = rules.last().is_some_and(|last| {
+            last.chars().any(|character| character.is_ascii_digit())
+                || last.ends_with("off")
+                || last.ends_with("closed")
+        });
+        match rules.last_mut() {
+            Some(last) if !(starts_with_selector && has_times) => {
+                last.push(',');
+                last.push_str(part);
+            }
+            _ => rules.push(part.to_string()),
+        }
+    }
+    rules
+}
+
+/// The days selected by a rule and its times.
+fn parse_rule(rule: &str) -> Result<([bool; 7], Times), OpeningHoursError> {
+    let rule = rule.trim();
+    let times_start = rule
+        .find(|character: char| character.is_ascii_digit())
+        .into_iter()
+        .chain(
+            ["off", "closed"]
+                .iter()
+                .filter_map(|keyword| rule.find(keyword)),
+        )
+        .min()
+        .unwrap_or(rule.len());
+    let (selector, times) = rule.split_at(times_start);
+
+    let selected = parse_weekdays(selector)?;
+    let times = times.trim();
+    let times = match times {
+        "" => {
+            return Err(OpeningHoursError(format!(
+                "no times for the rule \"{}\"",
+                rule
+            )));
+        }
+        "off" | "closed" => Times::Closed,
+        "24/7" => Times::Open(vec![(0, DAY)]),
+        _ => Times::Open(
+            times
+                .split(',')
+                .map(parse_span)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+    };
+    Ok((selected, times))
+}
+
+/// The weekdays of a selector like `Mo-Fr,Su`, every day for an empty one.
+fn parse_weekdays(selector: &str) -> Result<[bool; 7], OpeningHoursError> {
+    let selector: String = selector
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .collect();
+    if selector.is_empty() {
+        return Ok([true; 7]);
+    }
+
+    let mut selected = [false; 7];
+    for item in selector.split(',').filter(|item| !item.is_empty()) {
+        let (first, last) = match item.split_once('-') {
+            Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+            None => (parse_weekday(item)?, parse_weekday(item)?),
+        };
+        // `Sa-Mo` wraps around the end of the week.
+        let mut day = first;
+        loop {
+            selected[day] = true;
+            if day == last {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(selected)
+}
+
+/// The index of a weekday, from its OSM abbreviation or its English name.
+fn parse_weekday(value: &str) -> Result<usize, OpeningHoursError> {
+    let lowercase = value.to_lowercase();
+    WEEKDAYS
+        .iter()
+        .zip(WEEKDAY_NAMES)
+        .position(|(day, name)| lowercase == *day || lowercase == name)
+        .ok_or_else(|| OpeningHoursError(format!("unsupported selector \"{}\"", value)))
+}
+
+/// A time span like `08:00-12:00`, its end is after midnight when it isn't after its start.
+fn parse_span(span: &str) -> Result<(u16, u16), OpeningHoursError> {
+    let invalid = || OpeningHoursError(format!("invalid time span \"{}\"", span.trim()));
+    let (start, end) = span.trim().split_once('-').ok_or_else(invalid)?;
+    let start = parse_time(start).ok_or_else(invalid)?;
+    let mut end = parse_time(end).ok_or_else(invalid)?;
+    if start >= DAY {
+        return Err(invalid());
+    }
+    if end <= start {
+        end += DAY;
+    }
+    if end > 2 * DAY {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Minutes since midnight of `HH:MM`, up to 48:00 for times of the next day.
+fn parse_time(time: &str) -> Option<u16> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    if minutes.len() != 2 || hours.is_empty() || hours.len() > 2 {
+        return None;
+    }
+    let hours: u16 = hours.parse().ok()?;
+    let minutes: u16 = minutes.parse().ok()?;
+    (hours <= 48 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Sorts the spans of a day and joins the overlapping ones.
+fn merge_spans(spans: &mut Vec<(u16, u16)>) {
+    spans.sort();
+    let mut merged: Vec<(u16, u16)> = Vec::with_capacity(spans.len());
+    for &(start, end) in spans.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *spans = merged;
+}
+
+fn format_time(minutes: u16) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+impl WeeklySchedule {
+    /// The schedule as a PostgreSQL `int4multirange` of minutes since Monday midnight,
+    /// which the backend checks a local time against.
+    pub fn to_week_ranges(&self) -> String {
+        let mut ranges = vec![];
+        for (day, spans) in self.days.iter().enumerate() {
+            let offset = day as u32 * DAY as u32;
+            for &(start, end) in spans {
+                ranges.push(format!(
+                    "[{},{})",
+                    offset + start as u32,
+                    offset + end as u32
+                ));
+            }
+        }
+        format!("{{{}}}", ranges.join(","))
+    }
+}
+
+/// Serialized as the time spans of each day, e.g. `{"mo": [["08:00", "12:00"]], ...}`.
+impl Serialize for WeeklySchedule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(7))?;
+        for (day, spans) in WEEKDAYS.iter().zip(&self.days) {
+            let spans: Vec<[String; 2]> = spans
+                .iter()
+                .map(|&(start, end)| [format_time(start), format_time(end)])
+                .collect();
+            map.serialize_entry(day, &spans)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn days(value: &str) -> [Vec<(u16, u16)>; 7] {
+        parse_opening_hours(value).unwrap().days
+    }
+
+    #[test]
+    fn test_parse_weekdays_and_spans() {
+        let days = days("Mo-Fr 08:00-12:00,14:00-18:00; Sa 09:00-12:00");
+        for day in &days[0..5] {
+            assert_eq!(day, &vec![(480, 720), (840, 1080)]);
+        }
+        assert_eq!(days[5], vec![(540, 720)]);
+        assert!(days[6].is_empty());
+    }
+
+    #[test]
+    fn test_parse_24_7_and_all_days() {
+        assert!(days("24/7").iter().all(|day| day == &vec![(0, DAY)]));
+        assert!(
+            days("10:00-20:00")
+                .iter()
+                .all(|day| day == &vec![(600, 1200)])
+        );
+        assert_eq!(days("Saturday-Monday 10:00-12:00")[0], vec![(600, 720)]);
+    }
+
+    #[test]
+    fn test_parse_later_rules_override() {
+        let days = days("Mo-Su 08:00-20:00; Su off");
+        assert_eq!(days[0], vec![(480, 1200)]);
+        assert!(days[6].is_empty());
+
+        // An additional rule adds to the days it selects.
+        let days = self::days("Mo-Fr 08:00-12:00, We 14:00-18:00");
+        assert_eq!(days[2], vec![(480, 720), (840, 1080)]);
+        assert_eq!(days[3], vec![(480, 720)]);
+    }
+
+    #[test]
+    fn test_parse_past_midnight() {
+        let days = days("Fr,Sa 22:00-02:00; Su-Mo 18:00-24:00");
+        assert_eq!(days[4], vec![(1320, DAY)]);
+        assert_eq!(days[5], vec![(0, 120), (1320, DAY)]);
+        assert_eq!(days[6], vec![(0, 120), (1080, DAY)]);
+        assert_eq!(days[0], vec![(1080, DAY)]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_opening_hours("").is_err());
+        assert!(parse_opening_hours("Mo-Fr").is_err());
+        assert!(parse_opening_hours("Mo-Fr 08:00-18:00+").is_err());
+        assert!(parse_opening_hours("Mo-Fr 8h-18h").is_err());
+        assert_eq!(
+            parse_opening_hours("Mo 25:00-26:00")
+                .unwrap_err()
+                .to_string(),
+            "invalid time span \"25:00-26:00\""
+        );
+    }
+
+    fn error(value: &str) -> String {
+        parse_opening_hours(value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_parse_unsupported_syntax() {
+        let months = "month and date selectors are not supported";
+        assert_eq!(error("Jan-Mar Mo-Fr 08:00-12:00"), months);
+        assert_eq!(error("Mo-Fr 08:00-18:00; Dec 25 off"), months);
+        assert_eq!(error("2025 Mo-Fr 08:00-18:00"), months);
+        assert_eq!(error("easter off"), months);
+        assert_eq!(
+            error("week 01-26 Mo-Fr 08:00-18:00"),
+            "week selectors are not supported"
+        );
+        assert_eq!(error("sunrise-sunset"), "variable times are not supported");
+        assert_eq!(
+            error("Mo-Fr 08:00-dusk"),
+            "variable times are not supported"
+        );
+        assert_eq!(
+            error("Mo-Fr 08:00-12:00 || \"on appointment\""),
+            "fallback rules are not supported"
+        );
+        let holidays = "public and school holiday rules are not supported";
+        // A holiday rule changes the times on days a weekly schedule can't tell apart.
+        assert_eq!(error("Mo-Sa 08:00-20:00; PH off"), holidays);
+        assert_eq!(error("Mo-Fr,PH 08:00-12:00"), holidays);
+        assert_eq!(error("Mo-Fr 08:00-18:00; SH Mo-Fr 09:00-12:00"), holidays);
+        // Words in comments don't count.
+        assert_eq!(
+            error("Mo-Fr 08:00-18:00 \"closed in May\""),
+            "invalid time span \"08:00-18:00 \"closed in May\"\""
+        );
+    }
+
+    #[test]
+    fn test_to_week_ranges() {
+        let schedule = parse_opening_hours("Mo 08:00-12:00; Su 23:00-01:00").unwrap();
+        assert_eq!(
+            schedule.to_week_ranges(),
+            "{[0,60),[480,720),[10020,10080)}"
+        );
+        assert_eq!(
+            parse_opening_hours("Mo-Su off").unwrap().to_week_ranges(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let schedule = parse_opening_hours("Mo 08:00-12:00; Su 18:00-24:00").unwrap();
+        assert_eq!(
+            serde_json::to_value(&schedule).unwrap(),
+            serde_json::json!({
+                "mo": [["08:00", "12:00"]],
+                "tu": [], "we": [], "th": [], "fr": [], "sa": [],
+                "su": [["18:00", "24:00"]]
+            })
+        );
+    }
+}
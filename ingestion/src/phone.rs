@@ -0,0 +1,80 @@
+use phonenumber::country;
+
+/// The phone numbers of a POI, split out of its raw `phone` value.
+#[derive(Debug, Default, PartialEq)]
+pub struct Phones {
+    /// The valid numbers in E.164 format, e.g. `+33123456789`.
+    pub numbers: Vec<String>,
+    /// The parts of the raw value that aren't valid phone numbers.
+    pub invalid: Vec<String>,
+}
+
+/// Normalises the `;` or `,` separated numbers of `raw` to E.164.
+///
+/// Numbers without an international prefix are read as numbers of `country_code`, the
+/// country the POI was geocoded into. Duplicates are only kept once.
+pub fn normalize_phones(raw: &str, country_code: &str) -> Phones {
+    let region = country_code.parse::<country::Id>().ok();
+    let mut phones = Phones::default();
+    for part in raw
+        .split([';', ','])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let number = phonenumber::parse(region, part)
+            .ok()
+            .filter(phonenumber::is_valid)
+            .map(|number| number.format().mode(phonenumber::Mode::E164).to_string());
+        match number {
+            Some(value) if !phones.numbers.contains(&value) => phones.numbers.push(value),
+            Some(_) => {}
+            None => phones.invalid.push(part.to_string()),
+        }
+    }
+    phones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_phones_national_and_international() {
+        assert_eq!(
+            normalize_phones("01 42 68 53 00", "FR").numbers,
+            vec!["+33142685300"]
+        );
+        assert_eq!(
+            normalize_phones("+33 1 42 68 53 00", "FR").numbers,
+            vec!["+33142685300"]
+        );
+        // The prefix wins over the country of the POI.
+        assert_eq!(
+            normalize_phones("+44 20 7946 0958", "FR").numbers,
+            vec!["+442079460958"]
+        );
+        assert_eq!(
+            normalize_phones("(212) 555-0123", "US").numbers,
+            vec!["+12125550123"]
+        );
+    }
+
+    #[test]
+    fn test_normalize_phones_several_numbers() {
+        let phones = normalize_phones("01 42 68 53 00; +33142685300;+33 6 12 34 56 78", "FR");
+        assert_eq!(phones.numbers, vec!["+33142685300", "+33612345678"]);
+        assert!(phones.invalid.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_phones_invalid() {
+        let phones = normalize_phones("call us; 01 42 68 53 00; 123", "FR");
+        assert_eq!(phones.numbers, vec!["+33142685300"]);
+        assert_eq!(phones.invalid, vec!["call us", "123"]);
+
+        // Without a known country, only international numbers can be read.
+        let phones = normalize_phones("01 42 68 53 00", "XX");
+        assert!(phones.numbers.is_empty());
+        assert_eq!(phones.invalid, vec!["01 42 68 53 00"]);
+    }
+}
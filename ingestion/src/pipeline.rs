@@ -0,0 +1,401 @@
+use std::collections::BTreeMap;
+use std::thread;
+
+use crossbeam_channel::{SendError, Sender, bounded, unbounded};
+use log::{error, info, warn};
+
+use crate::error::IngestionError;
+use crate::geocoder::Geocoder;
+use crate::model::{POI, RunStats};
+use crate::poi::extract_features;
+use crate::report::{DroppedFeatures, FileStatus, QualityIssues, RunReport, SkipReason, Stage};
+use crate::source::{Source, SourceEntry, SourceFile, SourceReader};
+
+/// What happened to a single file of the source.
+#[derive(Debug)]
+enum Outcome {
+    Ingested { rows: usize, quality: QualityIssues },
+    Skipped(SkipReason),
+    Failed(Stage, IngestionError),
+}
+
+#[derive(Debug)]
+struct FileOutcome {
+    index: usize,
+    entry: SourceEntry,
+    outcome: Outcome,
+    dropped: DroppedFeatures,
+}
+
+struct ParsedFile {
+    index: usize,
+    entry: SourceEntry,
+    pois: Vec<POI>,
+    dropped: DroppedFeatures,
+    quality: QualityIssues,
+}
+
+/// Ingests the `entries` of `source` with a pool of threads.
+///
+/// `workers` threads read, parse and geocode the files, one thread per element of
+/// `writers` hands them over to `write` with its own state, usually its own database
+/// connections. Both stages are linked by bounded queues, so a slow database holds the
+/// parsing back instead of piling up parsed files in memory. The outcome of each file is
+/// logged in the order of `entries`, whatever order the threads finish in.
+///
+/// A file that fails doesn't stop the others, it ends up in the returned report together
+/// with the spiders seen in the source, the ones of files that failed or whose features
+/// were all dropped included.
+pub fn run_pipeline<W: Send>(
+    source: &Source,
+    entries: Vec<SourceEntry>,
+    workers: usize,
+    writers: Vec<W>,
+    geocoder: &dyn Geocoder,
+    write: impl Fn(&mut W, Vec<POI>) -> Result<(), IngestionError> + Sync,
+    stats: &mut RunStats,
+) -> (Vec<String>, RunReport) {
+    let total = entries.len();
+    info!(
+        "ingesting {} files with {} workers and {} writers",
+        total,
+        workers,
+        writers.len()
+    );
+    let mut seen_spiders = vec![];
+    let mut report = RunReport::default();
+
+    thread::scope(|scope| {
+        let (job_tx, job_rx) = bounded::<(usize, SourceEntry)>(workers);
+        // Every parsed file holds all its POIs, keep only a few of them waiting.
+        let (parsed_tx, parsed_rx) = bounded::<ParsedFile>(writers.len());
+        let (done_tx, done_rx) = unbounded::<FileOutcome>();
+
+        scope.spawn(move || {
+            for job in entries.into_iter().enumerate() {
+                if job_tx.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        for _ in 0..workers {
+            let job_rx = job_rx.clone();
+            let parsed_tx = parsed_tx.clone();
+            let done_tx = done_tx.clone();
+            scope.spawn(move || {
+                // Opened with the first file, so a failure is reported against it.
+                let mut reader: Option<SourceReader> = None;
+                let extract = |file: SourceFile| extract_features(file, geocoder);
+                for (index, entry) in job_rx {
+                    let extracted = match &mut reader {
+                        Some(value) => value.read(&entry, extract),
+                        None => source.reader().and_then(|mut value| {
+                            let extracted = value.read(&entry, extract);
+                            reader = Some(value);
+                            extracted
+                        }),
+                    };
+                    let (outcome, dropped) = match extracted {
+                        Err(why) => (
+                            Outcome::Failed(Stage::Read, why),
+                            DroppedFeatures::default(),
+                        ),
+                        Ok(Err(why)) => (
+                            Outcome::Failed(Stage::Parse, why),
+                            DroppedFeatures::default(),
+                        ),
+                        Ok(Ok(extracted)) => match extracted.pois {
+                            Err(reason) => (Outcome::Skipped(reason), extracted.dropped),
+                            Ok(pois) => {
+                                let parsed = ParsedFile {
+                                    index,
+                                    entry,
+                                    pois,
+                                    dropped: extracted.dropped,
+                                    quality: extracted.quality,
+                                };
+                                match parsed_tx.send(parsed) {
+                                    Ok(()) => continue,
+                                    // All the writers are gone, report the file before stopping.
+                                    Err(SendError(parsed)) => {
+                                        send_outcome(
+                                            &done_tx,
+                                            parsed.index,
+                                            parsed.entry,
+                                            Outcome::Failed(
+                                                Stage::Write,
+                                                std::io::Error::other("no writer left").into(),
+                                            ),
+                                            parsed.dropped,
+                                        );
+                                        break;
+                                    }
+                                }
+                            }
+                        },
+                    };
+                    send_outcome(&done_tx, index, entry, outcome, dropped);
+                }
+            });
+        }
+
+        for mut writer in writers {
+            let parsed_rx = parsed_rx.clone();
+            let done_tx = done_tx.clone();
+            let write = &write;
+            scope.spawn(move || {
+                for parsed in parsed_rx {
+                    let rows = parsed.pois.len();
+                    let outcome = match write(&mut writer, parsed.pois) {
+                        Ok(()) => Outcome::Ingested {
+                            rows,
+                            quality: parsed.quality,
+                        },
+                        Err(why) => Outcome::Failed(Stage::Write, why),
+                    };
+                    send_outcome(
+                        &done_tx,
+                        parsed.index,
+                        parsed.entry,
+                        outcome,
+                        parsed.dropped,
+                    );
+                }
+            });
+        }
+        // Only the threads hold the channels now, so the loop below ends with them.
+        drop((job_rx, parsed_tx, parsed_rx, done_tx));
+
+        let mut record = |outcome: FileOutcome| {
+            let position = format!("[{}/{}]", outcome.index + 1, total);
+            let entry = outcome.entry;
+            stats.features_dropped += outcome.dropped.total() as i64;
+            let mut features = 0;
+            // The POIs are keyed on the spider of their file, see `extract_features`.
+            let status = match outcome.outcome {
+                Outcome::Ingested { rows, quality } => {
+                    features = rows;
+                    report.quality.merge(&quality);
+                    seen_spiders.push(entry.spider.clone());
+                    stats.rows_ingested += rows as i64;
+                    stats.files_ingested += 1;
+                    info!("{} File {} successfuly ingested", position, entry.name);
+                    FileStatus::Ingested
+                }
+                Outcome::Skipped(reason) => {
+                    // Only an empty file tells the spider has no POIs left, a file whose
+                    // features were all dropped keeps the POIs of the previous run.
+                    if reason == SkipReason::NoValidPoi {
+                        seen_spiders.push(entry.spider.clone());
+                    }
+                    stats.files_skipped += 1;
+                    warn!("{} File {} skipped: {}", position, entry.name, reason);
+                    FileStatus::Skipped { reason }
+                }
+                Outcome::Failed(stage, why) => {
+                    // A broken file keeps the POIs of its spider alive.
+                    seen_spiders.push(entry.spider.clone());
+                    stats.files_failed += 1;
+                    error!(
+                        "{} File {} failed at the {} stage: {}",
+                        position, entry.name, stage, why
+                    );
+                    FileStatus::Failed {
+                        stage,
+                        reason: why.to_string(),
+                    }
+                }
+            };
+            report.record(entry.name, entry.spider, status, features, outcome.dropped);
+        };
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for outcome in done_rx {
+            pending.insert(outcome.index, outcome);
+            while let Some(outcome) = pending.remove(&next) {
+                next += 1;
+                record(outcome);
+            }
+        }
+        // A file that never got an outcome leaves a gap, the ones after it are still reported.
+        for (_, outcome) in pending {
+            record(outcome);
+        }
+    });
+
+    (seen_spiders, report)
+}
+
+fn send_outcome(
+    done_tx: &Sender<FileOutcome>,
+    index: usize,
+    entry: SourceEntry,
+    outcome: Outcome,
+    dropped: DroppedFeatures,
+) {
+    // The receiving end lives until all threads are done.
+    let _ = done_tx.send(FileOutcome {
+        index,
+        entry,
+        outcome,
+        dropped,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geocoder::CountryBoundariesGeocoder;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    #[test]
+    fn test_run_pipeline() {
+        let source = Source::from_path(Path::new(FIXTURES));
+        let entries = source.entries(&[]).unwrap();
+        let written = Mutex::new(vec![]);
+        let mut stats = RunStats::default();
+
+        let (mut seen, report) = run_pipeline(
+            &source,
+            entries,
+            3,
+            vec![0, 1],
+            &CountryBoundariesGeocoder,
+            |count: &mut usize, pois| {
+                *count += 1;
+                written.lock().unwrap().push(pois.len());
+                Ok(())
+            },
+            &mut stats,
+        );
+
+        seen.sort();
+        seen.dedup();
+        // The skipped file has no POIs to keep.
+        assert_eq!(seen, vec!["spider_a", "spider_b"]);
+        assert_eq!(stats.rows_ingested, 3);
+        assert_eq!(stats.files_ingested, 2);
+        assert_eq!(stats.files_skipped, 1);
+        assert_eq!(report.total_files, 3);
+        assert_eq!(report.failed_files, 0);
+        let mut written = written.into_inner().unwrap();
+        written.sort();
+        assert_eq!(written, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_run_pipeline_write_failure() {
+        let source = Source::from_path(Path::new(FIXTURES));
+        let entries = source.entries(&[]).unwrap();
+        let mut stats = RunStats::default();
+
+        let (mut seen, report) = run_pipeline(
+            &source,
+            entries,
+            1,
+            vec![()],
+            &CountryBoundariesGeocoder,
+            |_, pois| match pois.len() {
+                1 => Err(std::io::Error::other("connection lost").into()),
+                _ => Ok(()),
+            },
+            &mut stats,
+        );
+
+        // The other files still went through.
+        assert_eq!(stats.files_ingested, 1);
+        assert_eq!(stats.files_failed, 1);
+        assert_eq!(stats.rows_ingested, 2);
+        assert_eq!(report.failed_files, 1);
+        // The POIs of the failed file are kept from the previous run.
+        seen.sort();
+        assert_eq!(seen, vec!["spider_a", "spider_b"]);
+        assert!(matches!(
+            &report.files.iter().find(|file| file.spider == "spider_b").unwrap().status,
+            FileStatus::Failed { stage: Stage::Write, reason } if reason.contains("connection lost")
+        ));
+    }
+
+    #[test]
+    fn test_run_pipeline_keeps_spiders_without_valid_poi() {
+        let dir = tempdir::TempDir::new("pipeline").expect("Failed to create temp dir");
+        // Every feature of the file lost its geometry, the spider isn't gone for all that.
+        std::fs::write(
+            dir.path().join("spider_broken.geojson"),
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","id":"1","properties":{"@spider":"spider_broken","@source_uri":"https://example.com"},"geometry":null}]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("spider_gone.geojson"), "").unwrap();
+        let source = Source::from_path(dir.path());
+        let entries = source.entries(&[]).unwrap();
+        let mut stats = RunStats::default();
+
+        let (seen, report) = run_pipeline(
+            &source,
+            entries,
+            1,
+            vec![()],
+            &CountryBoundariesGeocoder,
+            |_, _| Ok(()),
+            &mut stats,
+        );
+
+        assert_eq!(seen, vec!["spider_broken"]);
+        assert_eq!(stats.files_skipped, 2);
+        assert_eq!(stats.features_dropped, 1);
+        assert!(matches!(
+            &report
+                .files
+                .iter()
+                .find(|file| file.spider == "spider_broken")
+                .unwrap()
+                .status,
+            FileStatus::Skipped {
+                reason: SkipReason::NoValidPoi
+            }
+        ));
+    }
+
+    #[test]
+    fn test_run_pipeline_keys_spiders_on_their_file() {
+        let dir = tempdir::TempDir::new("pipeline").expect("Failed to create temp dir");
+        // The file was renamed without its `@spider`, or the other way round.
+        std::fs::write(
+            dir.path().join("spider_renamed.geojson"),
+            r#"{"type":"FeatureCollection","features":[{"type":"Feature","id":"1","properties":{"@spider":"spider_old","@source_uri":"https://example.com"},"geometry":{"type":"Point","coordinates":[2.3276581,48.8805374]}}]}"#,
+        )
+        .unwrap();
+        let source = Source::from_path(dir.path());
+        let written = Mutex::new(vec![]);
+
+        let run = |fail: bool| {
+            let mut stats = RunStats::default();
+            run_pipeline(
+                &source,
+                source.entries(&[]).unwrap(),
+                1,
+                vec![()],
+                &CountryBoundariesGeocoder,
+                |_, pois: Vec<POI>| match fail {
+                    true => Err(std::io::Error::other("connection lost").into()),
+                    false => {
+                        written.lock().unwrap().push(pois[0].spider_id.clone());
+                        Ok(())
+                    }
+                },
+                &mut stats,
+            )
+            .0
+        };
+
+        // A failed file protects the same POIs that an ingested one writes.
+        assert_eq!(run(false), vec!["spider_renamed"]);
+        assert_eq!(*written.lock().unwrap(), vec!["spider_renamed"]);
+        assert_eq!(run(true), vec!["spider_renamed"]);
+    }
+}
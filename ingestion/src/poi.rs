@@ -1,133 +1,307 @@
-use crate::files::{is_file_empty, read_geojson};
-use crate::model::{Brand, BrandWithPOIs, Feature, Geometry, POI};
-use country_boundaries::{BOUNDARIES_ODBL_360X180, CountryBoundaries, LatLon};
+use std::collections::HashMap;
+
+use crate::address::Address;
+use crate::coordinates::{check_coordinates, flag_duplicate_coordinates};
+use crate::error::IngestionError;
+use crate::files::read_features;
+use crate::geocoder::Geocoder;
+use crate::model::{
+    Brand, Category, Feature, GeocodeStatus, Geometry, POI, Properties, Website, WebsiteSource,
+};
+use crate::opening_hours::parse_opening_hours;
+use crate::phone::normalize_phones;
+use crate::report::{DropReason, DroppedFeatures, QualityIssue, QualityIssues, SkipReason};
+use crate::source::SourceFile;
+use crate::timezone::find_timezone;
 use geo::Point;
-use geojson::JsonValue;
-use lazy_static::lazy_static;
-use log::{debug, error, warn};
-use std::path::Display;
+use log::debug;
 use url::Url;
-use walkdir::DirEntry;
 
-lazy_static! {
-    static ref BOUNDARIES: CountryBoundaries =
-        CountryBoundaries::from_reader(BOUNDARIES_ODBL_360X180)
-            .expect("error while initializing the country boundaries");
+/// The POIs of a file, with the features that had to be dropped and the quality issues
+/// of the POIs that were kept.
+pub struct ExtractedFile {
+    pub pois: Result<Vec<POI>, SkipReason>,
+    pub dropped: DroppedFeatures,
+    pub quality: QualityIssues,
 }
 
-pub fn extract_features(input_path: DirEntry) -> Option<BrandWithPOIs> {
-    let display = input_path.path().display();
-    if is_file_empty(&input_path) {
-        warn!("the file {} is empty, skipping it", display);
-        return None;
+/// Builds the POIs of a file, each with its own brand, keyed on the spider of the file.
+///
+/// Broken features are dropped and counted, only a file that isn't a GeoJSON
+/// `FeatureCollection` at all is an error.
+pub fn extract_features(
+    file: SourceFile,
+    geocoder: &dyn Geocoder,
+) -> Result<ExtractedFile, IngestionError> {
+    let display = file.name;
+    let spider = file.spider;
+    let mut dropped = DroppedFeatures::default();
+    if file.is_empty {
+        debug!("the file {} is empty, skipping it", display);
+        return Ok(ExtractedFile {
+            pois: Err(SkipReason::EmptyFile),
+            dropped,
+            quality: QualityIssues::default(),
+        });
     }
-    let content = match read_geojson(&input_path) {
-        Err(why) => {
-            warn!(
-                "the file {} is broken, skipping it. Error is: {}",
-                display, why
-            );
-            return None;
+    let mut pois: Vec<POI> = vec![];
+    let mut quality = QualityIssues::default();
+    read_features(file.reader, |feature| {
+        let feature = match feature {
+            Err(why) => {
+                debug!("error parsing a feature of {}: {}", display, why);
+                dropped.add(DropReason::InvalidFeature);
+                return;
+            }
+            Ok(value) => value,
+        };
+        match build_poi(feature, geocoder) {
+            // The run knows a spider by its file, even when `@spider` says otherwise.
+            Ok(mut poi) => {
+                poi.spider_id = spider.clone();
+                pois.push(poi);
+            }
+            Err(reason) => dropped.add(reason),
         }
-        Ok(value) => value.to_json_value(),
-    };
-    let brand = match extract_brand(&content) {
-        Some(value) => {
-            debug!("the file {} has a valid brand: {}", display, value.name);
-            value
+    })?;
+    // The last of the features sharing an id wins, like in the database.
+    let last_positions: HashMap<(String, String), usize> = pois
+        .iter()
+        .enumerate()
+        .map(|(position, poi)| ((poi.spider_id.clone(), poi.feature_id.clone()), position))
+        .collect();
+    let mut pois: Vec<POI> = pois
+        .into_iter()
+        .enumerate()
+        .filter_map(|(position, poi)| {
+            let key = (poi.spider_id.clone(), poi.feature_id.clone());
+            if last_positions[&key] == position {
+                return Some(poi);
+            }
+            debug!("the feature {} of {} is repeated", poi.feature_id, display);
+            dropped.add(DropReason::DuplicateId);
+            None
+        })
+        .collect();
+    for poi in &pois {
+        if !poi.invalid_phones.is_empty() {
+            quality.add(QualityIssue::InvalidPhone);
         }
-        None => {
-            warn!("the file {} has no valid brand, skipping it", display);
-            return None;
+        if poi.opening_hours_error.is_some() {
+            quality.add(QualityIssue::InvalidOpeningHours);
         }
-    };
-
-    let pois = build_pois(&content, &display);
+        if poi.postcode_valid == Some(false) {
+            quality.add(QualityIssue::InvalidPostcode);
+        }
+        match poi.geocode_status {
+            GeocodeStatus::Geocoded => {}
+            GeocodeStatus::AddressCountry => quality.add(QualityIssue::CountryFromAddress),
+            GeocodeStatus::NotGeocoded => quality.add(QualityIssue::NotGeocoded),
+        }
+    }
     if pois.is_empty() {
-        warn!("the file {} has no valid POIs, skipping it", display);
-        return None;
-    } else {
-        debug!("the file {} has {} valid POIs", display, pois.len());
+        return Ok(ExtractedFile {
+            pois: Err(SkipReason::NoValidPoi),
+            dropped,
+            quality,
+        });
+    }
+    // A file holds the features of a single spider.
+    flag_duplicate_coordinates(&mut pois);
+    for flag in pois.iter().flat_map(|poi| &poi.coordinate_flags) {
+        quality.add(QualityIssue::from(*flag));
     }
-    Some(BrandWithPOIs { brand, pois })
+    debug!("the file {} has {} valid POIs", display, pois.len());
+    Ok(ExtractedFile {
+        pois: Ok(pois),
+        dropped,
+        quality,
+    })
 }
 
-fn extract_brand(content: &JsonValue) -> Option<Brand> {
-    let feature = content["features"].as_array()?.first()?;
-
-    let feature: Feature = serde_json::from_value(feature.clone()).ok()?;
-
-    match (
-        feature.properties.brand,
-        feature.properties.brand_wikidata_id,
-        feature.properties.operator,
-        feature.properties.operator_wikidata_id,
-    ) {
-        (Some(name), Some(wikidata_id), _, _) | (_, _, Some(name), Some(wikidata_id)) => {
-            Some(Brand {
-                name,
-                wikidata_id: Some(wikidata_id),
-            })
-        }
-        (Some(name), _, _, _) => Some(Brand {
-            name,
-            wikidata_id: None,
-        }),
-        _ => None,
-    }
+/// The brand of a feature with its Wikidata id. A feature without one is kept without a
+/// brand, its operator is stored on its own.
+fn extract_brand(feature: &Feature) -> Option<Brand> {
+    let properties = &feature.properties;
+    let non_empty = |value: &Option<String>| {
+        value
+            .as_ref()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    non_empty(&properties.brand).map(|name| Brand {
+        name,
+        wikidata_id: non_empty(&properties.brand_wikidata_id),
+    })
 }
 
-fn build_pois(content: &JsonValue, file_path: &Display) -> Vec<POI> {
-    let mut pois: Vec<POI> = vec![];
-    // this will either assign the value or stop the function and returns None
-    let features = content["features"]
-        .as_array()
-        .unwrap_or_else(|| panic!("error when parsing the content for the file {}", file_path));
-
-    for feature in features {
-        let poi = match build_poi(feature) {
-            Some(value) => value,
-            None => {
-                continue;
-            }
-        };
-        pois.push(poi);
+/// The form brand names are matched on: spelling variants like "McDonald's", "McDonalds"
+/// and "McDonald’s" all become `mcdonalds`. Apostrophes are dropped, other punctuation
+/// separates words.
+pub fn normalize_brand_name(name: &str) -> String {
+    let mut words = String::with_capacity(name.len());
+    for character in name.chars().flat_map(char::to_lowercase) {
+        match character {
+            '\'' | '’' | '‘' | '`' | '´' => {}
+            _ if character.is_alphanumeric() => words.push(character),
+            _ => words.push(' '),
+        }
     }
-    pois
+    words.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-fn build_poi(feature: &JsonValue) -> Option<POI> {
-    let feature: Feature = match serde_json::from_str(&feature.to_string()) {
-        Err(why) => {
-            error!("error parsing the feature {}: {}", why, feature);
-            return None;
-        }
-        Ok(value) => value,
-    };
+fn build_poi(feature: Feature, geocoder: &dyn Geocoder) -> Result<POI, DropReason> {
+    let feature_id =
+        parse_feature_id(&feature.id, &feature.properties.r#ref).ok_or(DropReason::MissingId)?;
     let poi_name = parse_poi_name(&feature.properties.brand, &feature.properties.name);
     let website = parse_url(
         &feature.properties.website,
         &Some(feature.properties.source_uri.clone()),
     );
-    let point = parse_coordinates(&feature.geometry);
-    let country_code = reverse_geocode(&point)?;
-
-    Some(POI {
-        poi_name,
-        website,
-        spider_id: feature.properties.spider_id,
-        opening_hours: feature.properties.opening_hours,
-        phone: feature.properties.phone,
+    let point = parse_coordinates(&feature.geometry).ok_or(DropReason::MissingGeometry)?;
+    let location = geocoder.locate(&point);
+    // Points outside of the boundaries, e.g. offshore, are kept with the country of their
+    // address when it is given as a code.
+    let address_country = parse_country_code(&feature.properties.address_country);
+    let (boundary_ids, geocode_status) = match location.boundary_ids.is_empty() {
+        false => (location.boundary_ids, GeocodeStatus::Geocoded),
+        true => match address_country.clone() {
+            Some(value) => (vec![value], GeocodeStatus::AddressCountry),
+            None => (vec![], GeocodeStatus::NotGeocoded),
+        },
+    };
+    // We get the last one to get the biggest one.
+    let country_code = boundary_ids.last().cloned();
+    let country = country_code.as_deref().unwrap_or_default();
+    let geocoded_country = match geocode_status {
+        GeocodeStatus::Geocoded => country_code.as_deref(),
+        _ => None,
+    };
+    let coordinate_flags = check_coordinates(
+        &point,
+        geocoded_country,
+        address_country.as_deref(),
+        geocoder,
+    );
+    // The first one is the smallest, e.g. `US-TX` before `US`.
+    let subdivision_code = boundary_ids
+        .iter()
+        .find(|value| value.contains('-'))
+        .cloned();
+    let timezone = find_timezone(&point).map(|value| value.to_string());
+    let (opening_schedule, opening_hours_error) = match &feature.properties.opening_hours {
+        Some(value) => match parse_opening_hours(value) {
+            Ok(schedule) => (Some(schedule), None),
+            Err(why) => (None, Some(why.to_string())),
+        },
+        None => (None, None),
+    };
+    let phones = feature
+        .properties
+        .phone
+        .as_deref()
+        .map(|value| normalize_phones(value, country))
+        .unwrap_or_default();
+    let category = parse_category(&feature.properties);
+    let brand = extract_brand(&feature);
+    let address = Address {
         full_address: feature.properties.address_full,
+        street_address: feature.properties.address_street_address,
         house_number: feature.properties.address_housenumber,
         street_name: feature.properties.address_street,
-        street_address: feature.properties.address_street_address,
         city: feature.properties.address_city,
         zipcode: feature.properties.address_postcode,
         state: feature.properties.address_state,
+        ..Default::default()
+    }
+    .normalize(country);
+
+    Ok(POI {
+        poi_name,
+        website: website.as_ref().map(|value| value.url.clone()),
+        website_host: website.as_ref().map(|value| value.host.clone()),
+        website_source: website.map(|value| value.source),
+        spider_id: feature.properties.spider_id,
+        feature_id,
+        opening_hours: feature.properties.opening_hours,
+        opening_schedule,
+        opening_hours_error,
+        timezone,
+        phone: feature.properties.phone,
+        phones: phones.numbers,
+        invalid_phones: phones.invalid,
+        full_address: address.full_address,
+        house_number: address.house_number,
+        street_name: address.street_name,
+        street_address: address.street_address,
+        city: address.city,
+        zipcode: address.zipcode,
+        state: address.state,
+        address_derived: address
+            .derived
+            .iter()
+            .map(|value| value.to_string())
+            .collect(),
+        postcode_valid: address.postcode_valid,
         country: feature.properties.address_country,
         country_code,
-        point,
+        geocode_status,
+        coordinate_flags,
+        subdivision_code,
+        admin_areas: location.admin_areas,
+        point: Some(point),
+        poi_ref: feature.properties.r#ref,
+        branch: feature.properties.branch,
+        email: feature.properties.email,
+        contact_twitter: feature.properties.contact_twitter,
+        contact_facebook: feature.properties.contact_facebook,
+        image: feature.properties.image,
+        located_in: feature.properties.located_in,
+        located_in_wikidata_id: feature.properties.located_in_wikidata_id,
+        nsi_id: feature.properties.nsi_id,
+        end_date: feature.properties.end_date,
+        operator: feature.properties.operator,
+        operator_wikidata_id: feature.properties.operator_wikidata_id,
+        tags: feature.tags,
+        category,
+        brand,
+    })
+}
+
+/// The ATP `id` is stable across runs for a given spider, `ref` is the fallback.
+fn parse_feature_id(id: &str, r#ref: &Option<String>) -> Option<String> {
+    if !id.trim().is_empty() {
+        return Some(id.trim().to_string());
+    }
+    r#ref
+        .as_ref()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// Takes the first OSM feature tag set on the feature, in order of precedence: a fuel
+/// station with a shop is an `amenity=fuel`. Only the first of `;` separated values is kept.
+fn parse_category(properties: &Properties) -> Option<Category> {
+    let candidates = [
+        ("amenity", &properties.amenity),
+        ("shop", &properties.shop),
+        ("tourism", &properties.tourism),
+        ("leisure", &properties.leisure),
+        ("healthcare", &properties.healthcare),
+        ("office", &properties.office),
+        ("craft", &properties.craft),
+    ];
+    candidates.into_iter().find_map(|(key, value)| {
+        value
+            .as_deref()
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase().replace(' ', "_"))
+            .filter(|value| !value.is_empty())
+            .map(|value| Category {
+                key: key.to_string(),
+                value,
+            })
     })
 }
 
@@ -138,49 +312,103 @@ fn parse_poi_name(brand: &Option<String>, name: &Option<String>) -> Option<Strin
     }
 }
 
-fn parse_url(website: &Option<String>, source_uri: &Option<String>) -> Option<String> {
+/// The store page of a POI, from `website` or else the `@source_uri` the spider scraped.
+///
+/// The URL is normalised: a missing scheme defaults to `https`, the fragment and the
+/// `utm_*` tracking parameters are dropped. Only `http` and `https` URLs are kept.
+fn parse_url(website: &Option<String>, source_uri: &Option<String>) -> Option<Website> {
     // Try website first, then fall back to source_uri
-    let urls_to_try = [website, source_uri];
-
-    for url_opt in urls_to_try.into_iter().flatten() {
-        if let Ok(parsed_url) = Url::parse(url_opt) {
-            if let Some(host) = parsed_url.host_str() {
-                return Some(host.to_string());
-            }
+    let urls_to_try = [
+        (website, WebsiteSource::Website),
+        (source_uri, WebsiteSource::SourceUri),
+    ];
+
+    for (url_opt, source) in urls_to_try {
+        let website = url_opt.as_deref().and_then(normalize_url).and_then(|url| {
+            let host = url.host_str()?.to_string();
+            Some(Website {
+                url: url.to_string(),
+                host,
+                source,
+            })
+        });
+        if website.is_some() {
+            return website;
         }
     }
     None
 }
 
+fn normalize_url(value: &str) -> Option<Url> {
+    let value = value.trim();
+    let mut url = match Url::parse(value) {
+        Ok(url) => url,
+        // `www.example.com/store` has no scheme.
+        Err(url::ParseError::RelativeUrlWithoutBase) if !value.starts_with('.') => {
+            Url::parse(&format!("https://{}", value)).ok()?
+        }
+        Err(_) => return None,
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    url.set_fragment(None);
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !key.starts_with("utm_"))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    match query.is_empty() {
+        true => url.set_query(None),
+        false => {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+    }
+    Some(url)
+}
+
+/// An ISO 3166-1 alpha-2 code, `UK` being the common spelling of `GB`.
+fn parse_country_code(value: &Option<String>) -> Option<String> {
+    let code = value.as_ref()?.trim().to_uppercase();
+    match code.as_str() {
+        "UK" => Some(String::from("GB")),
+        _ if code.len() == 2 && code.chars().all(|character| character.is_ascii_uppercase()) => {
+            Some(code)
+        }
+        _ => None,
+    }
+}
+
 fn parse_coordinates(geometry: &Option<Geometry>) -> Option<Point> {
     geometry
         .as_ref()
         .map(|value| Point::new(value.coordinates[0], value.coordinates[1]))
 }
 
-fn reverse_geocode(point: &Option<Point>) -> Option<String> {
-    let (longitude, latitude) = match point {
-        Some(value) => (value.x(), value.y()),
-        None => return None,
-    };
-    let latlong = match LatLon::new(latitude, longitude) {
-        Err(why) => {
-            warn!("error parsing the lat/long for Point: {:#?}", point);
-            warn!("error: {}", why);
-            return None;
-        }
-        Ok(value) => value,
-    };
-    let ids = BOUNDARIES.ids(latlong);
-    // We get the last one to get the biggest one.
-    ids.last().map(|value| value.to_string())
-}
-
 #[cfg(test)]
 mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::files::parse_feature;
+    use crate::geocoder::CountryBoundariesGeocoder;
+    use crate::model::CoordinateFlag;
+
+    fn extract_spider_1_file(content: &str) -> Result<ExtractedFile, IngestionError> {
+        let file = SourceFile {
+            name: String::from("spider_1.geojson"),
+            spider: String::from("spider_1"),
+            is_empty: false,
+            reader: &mut content.as_bytes(),
+        };
+        extract_features(file, &CountryBoundariesGeocoder)
+    }
+
+    fn build_poi_from_json(feature: serde_json::Value) -> Option<POI> {
+        parse_feature(&feature.to_string())
+            .ok()
+            .and_then(|feature| build_poi(feature, &CountryBoundariesGeocoder).ok())
+    }
 
     #[test]
     fn test_parse_poi_name_with_name() {
@@ -202,6 +430,24 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_parse_feature_id_with_id() {
+        let result = parse_feature_id("abc", &Some(String::from("123")));
+        assert_eq!(result, Some(String::from("abc")));
+    }
+
+    #[test]
+    fn test_parse_feature_id_fallback_to_ref() {
+        let result = parse_feature_id(" ", &Some(String::from("123")));
+        assert_eq!(result, Some(String::from("123")));
+    }
+
+    #[test]
+    fn test_parse_feature_id_none() {
+        let result = parse_feature_id("", &None);
+        assert!(result.is_none());
+    }
+
     #[test]
     fn test_parse_url_with_website() {
         let result = parse_url(
@@ -209,7 +455,10 @@ mod tests {
             &Some(String::from("https://calendar.google.com/calendar/")),
         );
         assert!(result.is_some());
-        assert_eq!(result.unwrap(), String::from("doc.rust-lang.org"));
+        let website = result.unwrap();
+        assert_eq!(website.host, String::from("doc.rust-lang.org"));
+        assert_eq!(website.url, String::from("https://doc.rust-lang.org/"));
+        assert_eq!(website.source, WebsiteSource::Website);
     }
 
     #[test]
@@ -219,7 +468,32 @@ mod tests {
             &Some(String::from("https://calendar.google.com/calendar/")),
         );
         assert!(result.is_some());
-        assert_eq!(result.unwrap(), String::from("calendar.google.com"));
+        let website = result.unwrap();
+        assert_eq!(website.host, String::from("calendar.google.com"));
+        assert_eq!(
+            website.url,
+            String::from("https://calendar.google.com/calendar/")
+        );
+        assert_eq!(website.source, WebsiteSource::SourceUri);
+    }
+
+    #[test]
+    fn test_parse_url_keeps_the_store_page() {
+        let result = parse_url(
+            &Some(String::from(
+                "HTTPS://www.Example.com/stores/paris-42?utm_source=atp&id=42#map",
+            )),
+            &Some(String::from("https://example.com/stores")),
+        );
+        let website = result.unwrap();
+        assert_eq!(website.url, "https://www.example.com/stores/paris-42?id=42");
+        assert_eq!(website.host, "www.example.com");
+
+        let result = parse_url(&Some(String::from("www.example.com/store")), &None);
+        assert_eq!(result.unwrap().url, "https://www.example.com/store");
+
+        let result = parse_url(&Some(String::from("mailto:store@example.com")), &None);
+        assert!(result.is_none());
     }
 
     #[test]
@@ -245,39 +519,6 @@ mod tests {
         assert!(result.is_none());
     }
 
-    #[test]
-    fn test_reverse_geocode_fr() {
-        let result = reverse_geocode(&Some(Point::new(2.3276581, 48.8805374)));
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), String::from("FR"));
-    }
-
-    #[test]
-    fn test_reverse_geocode_en() {
-        let result = reverse_geocode(&Some(Point::new(-0.14405508452768728, 51.4893335)));
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), String::from("GB"));
-    }
-
-    #[test]
-    fn test_reverse_geocode_us() {
-        let result = reverse_geocode(&Some(Point::new(-74.0060152, 40.7127281)));
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), String::from("US"));
-    }
-
-    #[test]
-    fn test_reverse_geocode_none() {
-        let result = reverse_geocode(&None);
-        assert!(result.is_none());
-    }
-
-    #[test]
-    fn test_reverse_geocode_water() {
-        let result = reverse_geocode(&Some(Point::new(3.864293, 54.375721)));
-        assert!(result.is_none());
-    }
-
     #[test]
     fn test_build_poi_valid_feature() {
         let feature = serde_json::json!({
@@ -303,18 +544,38 @@ mod tests {
                 "addr:city": "Test City",
                 "addr:postcode": "12345",
                 "addr:state": "Test State",
-                "addr:country": "Test Country"
+                "addr:country": "Test Country",
+                "ref": "store-42",
+                "branch": "Downtown",
+                "email": "contact@example.com",
+                "contact:twitter": "@example",
+                "contact:facebook": "https://facebook.com/example",
+                "image": "https://example.com/store.jpg",
+                "located_in": "Test Mall",
+                "located_in:wikidata": "Q1",
+                "nsi_id": "testbrand-abc123",
+                "end_date": "2030-01-01",
+                "operator": "Test Operator",
+                "operator:wikidata": "Q2"
             }
         });
 
-        let result = build_poi(&feature);
+        let result = build_poi_from_json(feature);
         assert!(result.is_some());
         let poi = result.unwrap();
         assert_eq!(poi.poi_name, Some("Test POI".to_string()));
-        assert_eq!(poi.website, Some("example.com".to_string()));
+        assert_eq!(poi.website, Some("http://example.com/".to_string()));
+        assert_eq!(poi.website_host, Some("example.com".to_string()));
+        assert_eq!(poi.website_source, Some(WebsiteSource::Website));
         assert_eq!(poi.spider_id, "spider_1".to_string());
+        assert_eq!(poi.feature_id, "uuid".to_string());
         assert_eq!(poi.opening_hours, Some("24/7".to_string()));
+        assert!(poi.opening_schedule.is_some());
+        assert_eq!(poi.opening_hours_error, None);
+        assert_eq!(poi.timezone, Some("America/New_York".to_string()));
         assert_eq!(poi.phone, Some("+123456789".to_string()));
+        assert!(poi.phones.is_empty());
+        assert_eq!(poi.invalid_phones, vec!["+123456789".to_string()]);
         assert_eq!(
             poi.full_address,
             Some("123 Test St, Test City, Test Country".to_string())
@@ -326,12 +587,57 @@ mod tests {
         assert_eq!(poi.zipcode, Some("12345".to_string()));
         assert_eq!(poi.state, Some("Test State".to_string()));
         assert_eq!(poi.country, Some("Test Country".to_string()));
-        assert_eq!(poi.country_code, "US".to_string());
+        assert_eq!(poi.country_code, Some("US".to_string()));
+        assert_eq!(poi.geocode_status, GeocodeStatus::Geocoded);
+        assert!(poi.coordinate_flags.is_empty());
+        assert_eq!(poi.subdivision_code, Some("US-NY".to_string()));
         assert_eq!(poi.point, Some(Point::new(-74.0060152, 40.7127281)));
+        assert_eq!(poi.poi_ref, Some("store-42".to_string()));
+        assert_eq!(poi.branch, Some("Downtown".to_string()));
+        assert_eq!(poi.email, Some("contact@example.com".to_string()));
+        assert_eq!(poi.contact_twitter, Some("@example".to_string()));
+        assert_eq!(
+            poi.contact_facebook,
+            Some("https://facebook.com/example".to_string())
+        );
+        assert_eq!(poi.image, Some("https://example.com/store.jpg".to_string()));
+        assert_eq!(poi.located_in, Some("Test Mall".to_string()));
+        assert_eq!(poi.located_in_wikidata_id, Some("Q1".to_string()));
+        assert_eq!(poi.nsi_id, Some("testbrand-abc123".to_string()));
+        assert_eq!(poi.end_date, Some("2030-01-01".to_string()));
+        assert_eq!(poi.operator, Some("Test Operator".to_string()));
+        assert_eq!(poi.operator_wikidata_id, Some("Q2".to_string()));
+    }
+
+    #[test]
+    fn test_build_poi_invalid_opening_hours() {
+        let poi = build_poi_from_json(json!({
+            "id": "uuid",
+            "type": "Feature",
+            "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+            "properties": {
+                "@spider": "spider_1",
+                "@source_uri": "http://example.com",
+                "opening_hours": "Jan-Mar Mo-Fr 08:00-12:00"
+            }
+        }))
+        .unwrap();
+
+        // The POI is kept with the reason its hours couldn't be read.
+        assert_eq!(
+            poi.opening_hours,
+            Some("Jan-Mar Mo-Fr 08:00-12:00".to_string())
+        );
+        assert!(poi.opening_schedule.is_none());
+        assert_eq!(
+            poi.opening_hours_error,
+            Some("month and date selectors are not supported".to_string())
+        );
+        assert_eq!(poi.timezone, Some("Europe/Paris".to_string()));
     }
 
     #[test]
-    fn test_build_poi_invalid_feature() {
+    fn test_build_poi_not_geocoded() {
         let feature = serde_json::json!({
             "id": "uuid",
             "type": "Feature",
@@ -359,8 +665,12 @@ mod tests {
             }
         });
 
-        let result = build_poi(&feature);
-        assert!(result.is_none());
+        // Kept even though the point is in the sea and the country isn't a code.
+        let poi = build_poi_from_json(feature).unwrap();
+        assert_eq!(poi.country_code, None);
+        assert_eq!(poi.geocode_status, GeocodeStatus::NotGeocoded);
+        assert_eq!(poi.timezone, Some("Etc/GMT".to_string()));
+        assert!(poi.phones.is_empty());
     }
 
     #[test]
@@ -374,7 +684,7 @@ mod tests {
             "properties": {}
         });
 
-        let result = build_poi(&feature);
+        let result = build_poi_from_json(feature);
         assert!(result.is_none());
     }
 
@@ -403,7 +713,302 @@ mod tests {
             }
         });
 
-        let result = build_poi(&feature);
+        let result = build_poi_from_json(feature);
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_build_poi_drop_reasons() {
+        let feature = |id: &str, geometry: serde_json::Value| {
+            let raw = json!({
+                "id": id,
+                "type": "Feature",
+                "geometry": geometry,
+                "properties": {"@spider": "spider_1", "@source_uri": "http://example.com"}
+            });
+            build_poi(
+                parse_feature(&raw.to_string()).unwrap(),
+                &CountryBoundariesGeocoder,
+            )
+        };
+        let point = |x: f64, y: f64| json!({"type": "Point", "coordinates": [x, y]});
+
+        assert_eq!(
+            feature("", point(2.3276581, 48.8805374)).err(),
+            Some(DropReason::MissingId)
+        );
+        assert_eq!(
+            feature("uuid", serde_json::Value::Null).err(),
+            Some(DropReason::MissingGeometry)
+        );
+    }
+
+    #[test]
+    fn test_build_poi_without_id_keyed_on_ref() {
+        let feature = json!({
+            "type": "Feature",
+            "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+            "properties": {"@spider": "spider_1", "@source_uri": "http://example.com", "ref": "store-42"}
+        });
+        let poi = build_poi_from_json(feature).unwrap();
+        assert_eq!(poi.feature_id, "store-42");
+    }
+
+    #[test]
+    fn test_build_poi_outside_of_the_boundaries() {
+        let feature = |country: Option<&str>| {
+            let raw = json!({
+                "id": "uuid",
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [3.864293, 54.375721]},
+                "properties": {
+                    "@spider": "spider_1",
+                    "@source_uri": "http://example.com",
+                    "addr:country": country
+                }
+            });
+            build_poi(
+                parse_feature(&raw.to_string()).unwrap(),
+                &CountryBoundariesGeocoder,
+            )
+            .unwrap()
+        };
+
+        let poi = feature(Some("nl"));
+        assert_eq!(poi.country_code, Some(String::from("NL")));
+        assert_eq!(poi.geocode_status, GeocodeStatus::AddressCountry);
+        // The time zone comes from the point, at sea.
+        assert_eq!(poi.timezone, Some(String::from("Etc/GMT")));
+        assert_eq!(poi.subdivision_code, None);
+
+        let poi = feature(Some("UK"));
+        assert_eq!(poi.country_code, Some(String::from("GB")));
+
+        let poi = feature(Some("Netherlands"));
+        assert_eq!(poi.country_code, None);
+        assert_eq!(poi.geocode_status, GeocodeStatus::NotGeocoded);
+
+        let poi = feature(None);
+        assert_eq!(poi.country_code, None);
+        assert_eq!(poi.geocode_status, GeocodeStatus::NotGeocoded);
+    }
+
+    #[test]
+    fn test_extract_features_counts_dropped_features() {
+        let content = json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "id": "1",
+                    "type": "Feature",
+                    "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+                    "properties": {"@spider": "spider_1", "@source_uri": "http://example.com", "brand": "A"}
+                },
+                {"id": "2", "type": "Feature", "geometry": {"type": "LineString"}, "properties": {}},
+                {
+                    "id": "3",
+                    "type": "Feature",
+                    "geometry": {"type": "Point", "coordinates": [5.0, 0.0]},
+                    "properties": {"@spider": "spider_1", "@source_uri": "http://example.com"}
+                }
+            ]
+        })
+        .to_string();
+        let extracted = extract_spider_1_file(&content).unwrap();
+        assert_eq!(extracted.pois.unwrap().len(), 2);
+        assert_eq!(extracted.quality.to_string(), "not_geocoded=1");
+        assert_eq!(extracted.dropped.to_string(), "invalid_feature=1");
+    }
+
+    #[test]
+    fn test_extract_features_counts_quality_issues() {
+        let feature = |id: &str, phone: &str, opening_hours: &str| {
+            json!({
+                "id": id,
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+                "properties": {
+                    "@spider": "spider_1",
+                    "@source_uri": "http://example.com",
+                    "phone": phone,
+                    "opening_hours": opening_hours
+                }
+            })
+        };
+        let content = json!({
+            "type": "FeatureCollection",
+            "features": [
+                feature("1", "01 42 68 53 00", "Mo-Fr 08:00-18:00"),
+                feature("2", "01 42 68 53 00; n/a", "Mo-Fr 08:00-18:00"),
+                feature("3", "n/a", "sunrise-sunset")
+            ]
+        })
+        .to_string();
+
+        // The POIs are kept whatever their issues.
+        let extracted = extract_spider_1_file(&content).unwrap();
+        let pois = extracted.pois.unwrap();
+        assert_eq!(pois.len(), 3);
+        assert_eq!(pois[1].phones, vec!["+33142685300".to_string()]);
+        assert_eq!(pois[1].invalid_phones, vec!["n/a".to_string()]);
+        // All of them are at the same place.
+        assert_eq!(pois[0].coordinate_flags, vec![CoordinateFlag::Duplicate]);
+        assert_eq!(
+            extracted.quality.to_string(),
+            "invalid_phone=2, invalid_opening_hours=1, duplicate_coordinates=3"
+        );
+    }
+
+    #[test]
+    fn test_extract_features_drops_repeated_ids() {
+        let feature = |id: &str, name: &str| {
+            json!({
+                "id": id,
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+                "properties": {"@spider": "spider_1", "@source_uri": "http://example.com", "name": name}
+            })
+        };
+        let content = json!({
+            "type": "FeatureCollection",
+            "features": [feature("1", "First"), feature("2", "Other"), feature("1", "Last")]
+        })
+        .to_string();
+
+        let extracted = extract_spider_1_file(&content).unwrap();
+        let names: Vec<Option<String>> = extracted
+            .pois
+            .unwrap()
+            .into_iter()
+            .map(|poi| poi.poi_name)
+            .collect();
+        // The last copy is kept, in its own position.
+        assert_eq!(
+            names,
+            vec![Some(String::from("Other")), Some(String::from("Last"))]
+        );
+        assert_eq!(extracted.dropped.to_string(), "duplicate_id=1");
+    }
+
+    #[test]
+    fn test_extract_features_not_a_collection() {
+        assert!(matches!(
+            extract_spider_1_file("[]"),
+            Err(IngestionError::Json(_))
+        ));
+    }
+
+    fn feature_from_properties(properties: serde_json::Value) -> Feature {
+        let mut properties = properties;
+        properties["@spider"] = json!("spider_1");
+        properties["@source_uri"] = json!("http://example.com");
+        let raw = json!({"id": "uuid", "type": "Feature", "properties": properties});
+        parse_feature(&raw.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_category() {
+        let feature = feature_from_properties(json!({"shop": "supermarket"}));
+        assert_eq!(
+            parse_category(&feature.properties),
+            Some(Category {
+                key: String::from("shop"),
+                value: String::from("supermarket"),
+            })
+        );
+        // The amenity wins over the shop of a fuel station.
+        let feature = feature_from_properties(json!({"shop": "convenience", "amenity": "fuel"}));
+        assert_eq!(
+            parse_category(&feature.properties),
+            Some(Category {
+                key: String::from("amenity"),
+                value: String::from("fuel"),
+            })
+        );
+        let feature = feature_from_properties(json!({"healthcare": " Pharmacy;clinic"}));
+        assert_eq!(
+            parse_category(&feature.properties),
+            Some(Category {
+                key: String::from("healthcare"),
+                value: String::from("pharmacy"),
+            })
+        );
+        let feature = feature_from_properties(json!({"shop": ""}));
+        assert_eq!(parse_category(&feature.properties), None);
+        let feature = feature_from_properties(json!({"name": "Test"}));
+        assert_eq!(parse_category(&feature.properties), None);
+    }
+
+    #[test]
+    fn test_extract_brand() {
+        let feature = feature_from_properties(json!({
+            "brand": "Shell",
+            "brand:wikidata": "Q110716465",
+            "operator": "Franchisee Ltd",
+            "operator:wikidata": "Q1"
+        }));
+        let brand = extract_brand(&feature).unwrap();
+        assert_eq!(brand.name, "Shell");
+        assert_eq!(brand.wikidata_id, Some(String::from("Q110716465")));
+
+        // The brand wins even without its Wikidata id.
+        let feature = feature_from_properties(json!({"brand": "Shell", "operator:wikidata": "Q1"}));
+        let brand = extract_brand(&feature).unwrap();
+        assert_eq!(brand.name, "Shell");
+        assert_eq!(brand.wikidata_id, None);
+
+        // The operator is kept apart, it doesn't stand for a missing brand.
+        let feature =
+            feature_from_properties(json!({"operator": "City Council", "operator:wikidata": "Q2"}));
+        assert!(extract_brand(&feature).is_none());
+
+        let feature = feature_from_properties(json!({"brand": " ", "name": "Test"}));
+        assert!(extract_brand(&feature).is_none());
+    }
+
+    #[test]
+    fn test_normalize_brand_name() {
+        assert_eq!(normalize_brand_name("McDonald's"), "mcdonalds");
+        assert_eq!(normalize_brand_name("McDonalds"), "mcdonalds");
+        assert_eq!(normalize_brand_name("McDonald’s"), "mcdonalds");
+        assert_eq!(
+            normalize_brand_name("  Carrefour   Market "),
+            "carrefour market"
+        );
+        assert_eq!(normalize_brand_name("Marks & Spencer"), "marks spencer");
+        assert_eq!(
+            normalize_brand_name("Intermarché-Super"),
+            "intermarché super"
+        );
+    }
+
+    #[test]
+    fn test_extract_features_brand_per_feature() {
+        let feature = |id: &str, brand: Option<&str>| {
+            json!({
+                "id": id,
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [2.3276581, 48.8805374]},
+                "properties": {"@spider": "spider_1", "@source_uri": "http://example.com", "brand": brand}
+            })
+        };
+        let content = json!({
+            "type": "FeatureCollection",
+            "features": [feature("1", Some("Esso")), feature("2", Some("Esso Express")), feature("3", None)]
+        })
+        .to_string();
+        let pois = extract_spider_1_file(&content).unwrap().pois.unwrap();
+        let brands: Vec<Option<String>> = pois
+            .iter()
+            .map(|poi| poi.brand.as_ref().map(|brand| brand.name.clone()))
+            .collect();
+        assert_eq!(
+            brands,
+            vec![
+                Some(String::from("Esso")),
+                Some(String::from("Esso Express")),
+                None
+            ]
+        );
+    }
 }
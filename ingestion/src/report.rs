@@ -0,0 +1,518 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::error::IngestionError;
+use crate::model::CoordinateFlag;
+
+/// Where in the pipeline a file failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Opening or decompressing the file.
+    Read,
+    /// Parsing the GeoJSON of the file.
+    Parse,
+    /// Writing the brand and the POIs of the file into the databases.
+    Write,
+}
+
+/// Why a file was skipped without being an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    EmptyFile,
+    NoValidPoi,
+}
+
+/// Why a single feature of a file was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// The feature doesn't match the model, or isn't a point.
+    InvalidFeature,
+    /// The feature has neither an `id` nor a `ref`.
+    MissingId,
+    /// The feature has no coordinates.
+    MissingGeometry,
+    /// A later feature of the file has the same id, it replaces this one.
+    DuplicateId,
+}
+
+/// Something wrong with a value of a POI that is still ingested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityIssue {
+    /// At least one of the phone numbers couldn't be parsed.
+    InvalidPhone,
+    /// The opening hours couldn't be parsed into a weekly schedule.
+    InvalidOpeningHours,
+    /// The postcode doesn't have the format of the country.
+    InvalidPostcode,
+    /// The point isn't in any country, the country comes from the address.
+    CountryFromAddress,
+    /// The point isn't in any country and the address has no country code either.
+    NotGeocoded,
+    /// The latitude or the longitude is out of its range.
+    CoordinatesOutOfRange,
+    /// The point is at `(0, 0)`.
+    NullIsland,
+    /// The latitude and the longitude look the wrong way round.
+    SwappedCoordinates,
+    /// The point is in another country than the address.
+    CountryMismatch,
+    /// Another POI of the spider has the same coordinates.
+    DuplicateCoordinates,
+}
+
+/// Number of occurrences by kind, e.g. of dropped features by reason.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Counts<K: Ord>(BTreeMap<K, usize>);
+
+/// Number of dropped features by reason.
+pub type DroppedFeatures = Counts<DropReason>;
+
+/// Number of ingested POIs by quality issue.
+pub type QualityIssues = Counts<QualityIssue>;
+
+/// What happened to a file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FileStatus {
+    Ingested,
+    Skipped { reason: SkipReason },
+    Failed { stage: Stage, reason: String },
+}
+
+/// A file that didn't go through cleanly.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileReport {
+    pub file: String,
+    pub spider: String,
+    #[serde(flatten)]
+    pub status: FileStatus,
+    pub dropped: DroppedFeatures,
+}
+
+/// A change to the identity of the brands made while resolving the brands of a run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BrandChange {
+    /// A brand known by its name only got the Wikidata id of a feature.
+    Identified {
+        brand_id: i32,
+        name: String,
+        wikidata_id: String,
+    },
+    /// A brand known by its name only was folded into the brand with that Wikidata id.
+    Merged {
+        from_id: i32,
+        into_id: i32,
+        name: String,
+        wikidata_id: String,
+    },
+    /// A Wikidata id showed up for a name already used by a brand with another one.
+    Split {
+        brand_id: i32,
+        name: String,
+        wikidata_id: String,
+    },
+}
+
+/// What a run saw of a spider, whatever happened to its file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiderReport {
+    pub spider: String,
+    pub file: String,
+    pub status: FileStatus,
+    /// Number of POIs written, zero unless the file was ingested.
+    pub features: usize,
+    pub dropped: usize,
+}
+
+/// Summary of a run, logged at its end and saved with it.
+///
+/// Only the files that failed, were skipped or lost features are listed, `spiders` keeps
+/// every spider of the run for the `spider` table but isn't saved with the run.
+#[derive(Debug, Default, Serialize)]
+pub struct RunReport {
+    pub total_files: usize,
+    pub failed_files: usize,
+    pub dropped: DroppedFeatures,
+    pub quality: QualityIssues,
+    pub files: Vec<FileReport>,
+    pub brand_changes: Vec<BrandChange>,
+    #[serde(skip)]
+    pub spiders: Vec<SpiderReport>,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Read => "read",
+            Stage::Parse => "parse",
+            Stage::Write => "write",
+        })
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::EmptyFile => "the file is empty",
+            SkipReason::NoValidPoi => "the file has no valid POIs",
+        })
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DropReason::InvalidFeature => "invalid_feature",
+            DropReason::MissingId => "missing_id",
+            DropReason::MissingGeometry => "missing_geometry",
+            DropReason::DuplicateId => "duplicate_id",
+        })
+    }
+}
+
+impl fmt::Display for BrandChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BrandChange::Identified {
+                brand_id,
+                name,
+                wikidata_id,
+            } => write!(
+                f,
+                "brand {} ({}) identified as {}",
+                brand_id, name, wikidata_id
+            ),
+            BrandChange::Merged {
+                from_id,
+                into_id,
+                name,
+                wikidata_id,
+            } => write!(
+                f,
+                "brand {} ({}) merged into brand {} ({})",
+                from_id, name, into_id, wikidata_id
+            ),
+            BrandChange::Split {
+                brand_id,
+                name,
+                wikidata_id,
+            } => write!(
+                f,
+                "brand {} ({}) split from the other brands named {}",
+                brand_id, wikidata_id, name
+            ),
+        }
+    }
+}
+
+impl FileStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileStatus::Ingested => "ingested",
+            FileStatus::Skipped { .. } => "skipped",
+            FileStatus::Failed { .. } => "failed",
+        }
+    }
+
+    /// Why the file wasn't ingested.
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            FileStatus::Ingested => None,
+            FileStatus::Skipped { reason } => Some(reason.to_string()),
+            FileStatus::Failed { stage, reason } => Some(format!("{} stage: {}", stage, reason)),
+        }
+    }
+}
+
+impl fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            QualityIssue::InvalidPhone => "invalid_phone",
+            QualityIssue::InvalidOpeningHours => "invalid_opening_hours",
+            QualityIssue::InvalidPostcode => "invalid_postcode",
+            QualityIssue::CountryFromAddress => "country_from_address",
+            QualityIssue::NotGeocoded => "not_geocoded",
+            QualityIssue::CoordinatesOutOfRange => "coordinates_out_of_range",
+            QualityIssue::NullIsland => "null_island",
+            QualityIssue::SwappedCoordinates => "swapped_coordinates",
+            QualityIssue::CountryMismatch => "country_mismatch",
+            QualityIssue::DuplicateCoordinates => "duplicate_coordinates",
+        })
+    }
+}
+
+impl From<CoordinateFlag> for QualityIssue {
+    fn from(flag: CoordinateFlag) -> Self {
+        match flag {
+            CoordinateFlag::OutOfRange => QualityIssue::CoordinatesOutOfRange,
+            CoordinateFlag::NullIsland => QualityIssue::NullIsland,
+            CoordinateFlag::Swapped => QualityIssue::SwappedCoordinates,
+            CoordinateFlag::CountryMismatch => QualityIssue::CountryMismatch,
+            CoordinateFlag::Duplicate => QualityIssue::DuplicateCoordinates,
+        }
+    }
+}
+
+impl<K: Ord> Default for Counts<K> {
+    fn default() -> Self {
+        Counts(BTreeMap::new())
+    }
+}
+
+impl<K: Ord + Copy> Counts<K> {
+    pub fn add(&mut self, kind: K) {
+        *self.0.entry(kind).or_default() += 1;
+    }
+
+    pub fn merge(&mut self, other: &Counts<K>) {
+        for (kind, count) in &other.0 {
+            *self.0.entry(*kind).or_default() += count;
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.0.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<K: Ord + fmt::Display> fmt::Display for Counts<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(kind, count)| format!("{}={}", kind, count))
+            .collect();
+        f.write_str(&counts.join(", "))
+    }
+}
+
+impl RunReport {
+    pub fn record(
+        &mut self,
+        file: String,
+        spider: String,
+        status: FileStatus,
+        features: usize,
+        dropped: DroppedFeatures,
+    ) {
+        self.total_files += 1;
+        self.dropped.merge(&dropped);
+        self.spiders.push(SpiderReport {
+            spider: spider.clone(),
+            file: file.clone(),
+            status: status.clone(),
+            features,
+            dropped: dropped.total(),
+        });
+        if matches!(status, FileStatus::Failed { .. }) {
+            self.failed_files += 1;
+        }
+        if status != FileStatus::Ingested || !dropped.is_empty() {
+            self.files.push(FileReport {
+                file,
+                spider,
+                status,
+                dropped,
+            });
+        }
+    }
+
+    /// Fails when more than `threshold` of the files, between 0 and 1, failed.
+    pub fn check_threshold(&self, threshold: f64) -> Result<(), IngestionError> {
+        let ratio = self.failed_files as f64 / self.total_files.max(1) as f64;
+        if ratio > threshold {
+            return Err(IngestionError::TooManyFailures {
+                failed: self.failed_files,
+                total: self.total_files,
+                threshold,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn log(&self) {
+        info!(
+            "{} files read, {} failed, {} features dropped",
+            self.total_files,
+            self.failed_files,
+            self.dropped.total()
+        );
+        if !self.dropped.is_empty() {
+            info!("dropped features by reason: {}", self.dropped);
+        }
+        if !self.quality.is_empty() {
+            info!("POIs with quality issues: {}", self.quality);
+        }
+        for change in &self.brand_changes {
+            info!("{}", change);
+        }
+        for file in &self.files {
+            match &file.status {
+                FileStatus::Failed { stage, reason } => error!(
+                    "{} failed at the {} stage: {} (dropped: {})",
+                    file.file, stage, reason, file.dropped
+                ),
+                FileStatus::Skipped { reason } => warn!("{} skipped: {}", file.file, reason),
+                FileStatus::Ingested => {
+                    warn!("{} lost some features: {}", file.file, file.dropped)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dropped(reasons: &[DropReason]) -> DroppedFeatures {
+        let mut dropped = DroppedFeatures::default();
+        for reason in reasons {
+            dropped.add(*reason);
+        }
+        dropped
+    }
+
+    #[test]
+    fn test_record() {
+        let mut report = RunReport::default();
+        report.record(
+            String::from("a.geojson"),
+            String::from("a"),
+            FileStatus::Ingested,
+            4,
+            DroppedFeatures::default(),
+        );
+        report.record(
+            String::from("b.geojson"),
+            String::from("b"),
+            FileStatus::Ingested,
+            2,
+            dropped(&[
+                DropReason::MissingGeometry,
+                DropReason::MissingId,
+                DropReason::MissingGeometry,
+            ]),
+        );
+        report.record(
+            String::from("c.geojson"),
+            String::from("c"),
+            FileStatus::Failed {
+                stage: Stage::Write,
+                reason: String::from("connection lost"),
+            },
+            0,
+            dropped(&[DropReason::MissingId]),
+        );
+
+        // The clean file isn't listed.
+        assert_eq!(report.total_files, 3);
+        assert_eq!(report.failed_files, 1);
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.dropped.total(), 4);
+        assert_eq!(
+            report.dropped.to_string(),
+            "missing_id=2, missing_geometry=2"
+        );
+        // Every spider is kept, the clean one included.
+        assert_eq!(report.spiders.len(), 3);
+        assert_eq!(report.spiders[0].features, 4);
+        assert_eq!(report.spiders[1].dropped, 3);
+        assert_eq!(report.spiders[2].status.name(), "failed");
+        assert_eq!(
+            report.spiders[2].status.reason().as_deref(),
+            Some("write stage: connection lost")
+        );
+    }
+
+    #[test]
+    fn test_check_threshold() {
+        let mut report = RunReport::default();
+        for index in 0..10 {
+            let status = match index {
+                0 => FileStatus::Failed {
+                    stage: Stage::Parse,
+                    reason: String::from("invalid JSON"),
+                },
+                _ => FileStatus::Ingested,
+            };
+            report.record(
+                format!("{}.geojson", index),
+                index.to_string(),
+                status,
+                1,
+                DroppedFeatures::default(),
+            );
+        }
+        assert!(report.check_threshold(0.1).is_ok());
+        assert!(matches!(
+            report.check_threshold(0.05),
+            Err(IngestionError::TooManyFailures {
+                failed: 1,
+                total: 10,
+                ..
+            })
+        ));
+        assert!(RunReport::default().check_threshold(0.0).is_ok());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut report = RunReport::default();
+        report.record(
+            String::from("c.geojson"),
+            String::from("c"),
+            FileStatus::Failed {
+                stage: Stage::Read,
+                reason: String::from("invalid zip"),
+            },
+            0,
+            DroppedFeatures::default(),
+        );
+        report.quality.add(QualityIssue::InvalidPhone);
+        report.quality.add(QualityIssue::InvalidPhone);
+        report.brand_changes.push(BrandChange::Merged {
+            from_id: 3,
+            into_id: 1,
+            name: String::from("McDonalds"),
+            wikidata_id: String::from("Q38076"),
+        });
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "total_files": 1,
+                "failed_files": 1,
+                "dropped": {},
+                "quality": {"invalid_phone": 2},
+                "files": [{
+                    "file": "c.geojson",
+                    "spider": "c",
+                    "status": "failed",
+                    "stage": "read",
+                    "reason": "invalid zip",
+                    "dropped": {}
+                }],
+                "brand_changes": [{
+                    "action": "merged",
+                    "from_id": 3,
+                    "into_id": 1,
+                    "name": "McDonalds",
+                    "wikidata_id": "Q38076"
+                }]
+            })
+        );
+    }
+}
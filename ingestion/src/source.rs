@@ -0,0 +1,290 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::error::IngestionError;
+use crate::files::is_file_empty;
+use crate::unzip::unzip;
+
+/// Where the GeoJSON files of a run are read from.
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    /// The zip file published by ATP, downloaded or archived.
+    Zip(PathBuf),
+    /// A directory with the already extracted GeoJSON files.
+    Directory(PathBuf),
+}
+
+/// A GeoJSON file of a source, named after the spider that produced it.
+#[derive(Debug, Clone)]
+pub struct SourceEntry {
+    pub name: String,
+    pub spider: String,
+    pub is_empty: bool,
+}
+
+/// The content of a `SourceEntry`, as handed over by `SourceReader::read`.
+pub struct SourceFile<'a> {
+    pub name: String,
+    pub spider: String,
+    pub is_empty: bool,
+    pub reader: &'a mut dyn Read,
+}
+
+impl Source {
+    pub fn from_path(path: &Path) -> Source {
+        if path.is_dir() {
+            Source::Directory(path.to_path_buf())
+        } else {
+            Source::Zip(path.to_path_buf())
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Source::Zip(path) | Source::Directory(path) => path,
+        }
+    }
+
+    /// Metadata saved by the download stage next to the zip file, if there is one.
+    pub fn run_info_path(&self) -> Option<PathBuf> {
+        match self {
+            Source::Zip(path) => Some(path.with_extension("json")),
+            Source::Directory(_) => None,
+        }
+    }
+
+    /// Returns the directory holding the GeoJSON files, extracting the zip file into
+    /// `work_dir` first if needed.
+    pub fn extract(&self, work_dir: &Path) -> Result<PathBuf, IngestionError> {
+        match self {
+            Source::Zip(path) => {
+                unzip(path, work_dir)?;
+                Ok(work_dir.join("output"))
+            }
+            Source::Directory(path) => Ok(files_directory(path)),
+        }
+    }
+
+    /// Lists the GeoJSON files of the source, keeping only the given spiders when
+    /// `spiders` is not empty.
+    pub fn entries(&self, spiders: &[String]) -> Result<Vec<SourceEntry>, IngestionError> {
+        let is_wanted = |spider: &str| spiders.is_empty() || spiders.iter().any(|s| s == spider);
+        let mut entries = vec![];
+        match self {
+            Source::Zip(path) => {
+                let mut archive = ZipArchive::new(File::open(path)?)?;
+                info!("reading {} entries from {}", archive.len(), path.display());
+                for index in 0..archive.len() {
+                    let entry = archive.by_index_raw(index)?;
+                    if entry.is_dir() {
+                        continue;
+                    }
+                    let name = entry.name().to_string();
+                    let spider = spider_name(Path::new(&name));
+                    if !is_wanted(&spider) {
+                        debug!("skipping the entry {}", name);
+                        continue;
+                    }
+                    entries.push(SourceEntry {
+                        is_empty: entry.size() == 0,
+                        name,
+                        spider,
+                    });
+                }
+            }
+            Source::Directory(path) => {
+                for entry in WalkDir::new(files_directory(path))
+                    .max_depth(1)
+                    .into_iter()
+                    .filter_map(|f| f.ok())
+                    .filter(|e| e.path().is_file())
+                {
+                    let spider = spider_name(entry.path());
+                    if !is_wanted(&spider) {
+                        debug!("skipping the file {}", entry.path().display());
+                        continue;
+                    }
+                    entries.push(SourceEntry {
+                        name: entry.path().display().to_string(),
+                        spider,
+                        is_empty: is_file_empty(&entry),
+                    });
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Opens the source for reading, each thread needs its own reader.
+    pub fn reader(&self) -> Result<SourceReader, IngestionError> {
+        match self {
+            Source::Zip(path) => Ok(SourceReader::Zip(ZipArchive::new(File::open(path)?)?)),
+            Source::Directory(_) => Ok(SourceReader::Directory),
+        }
+    }
+}
+
+/// Gives access to the content of the entries of a source.
+pub enum SourceReader {
+    Zip(ZipArchive<File>),
+    Directory,
+}
+
+impl SourceReader {
+    /// Calls `handle` with the content of `entry`.
+    ///
+    /// Zip entries are decompressed while they are read, nothing is written to disk.
+    pub fn read<T>(
+        &mut self,
+        entry: &SourceEntry,
+        handle: impl FnOnce(SourceFile) -> T,
+    ) -> Result<T, IngestionError> {
+        let file = |reader| SourceFile {
+            name: entry.name.clone(),
+            spider: entry.spider.clone(),
+            is_empty: entry.is_empty,
+            reader,
+        };
+        match self {
+            SourceReader::Zip(archive) => {
+                let mut zip_file = archive.by_name(&entry.name)?;
+                Ok(handle(file(&mut zip_file)))
+            }
+            SourceReader::Directory => {
+                let mut fs_file = File::open(&entry.name)?;
+                Ok(handle(file(&mut fs_file)))
+            }
+        }
+    }
+}
+
+// Accept both the extracted `output` directory and its parent.
+fn files_directory(path: &Path) -> PathBuf {
+    let directory = if path.join("output").is_dir() {
+        path.join("output")
+    } else {
+        path.to_path_buf()
+    };
+    info!("reading the files from {}", directory.display());
+    directory
+}
+
+// ATP names every file after its spider, e.g. `output/mcdonalds_fr.geojson`.
+fn spider_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|value| value.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geocoder::CountryBoundariesGeocoder;
+    use crate::poi::extract_features;
+    use std::fs;
+    use std::io::Write;
+    use tempdir::TempDir;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+    // Zips the fixture files the same way ATP lays out its archive.
+    fn build_fixture_archive(dir: &Path) -> PathBuf {
+        let zip_path = dir.join("output.zip");
+        let mut writer = ZipWriter::new(File::create(&zip_path).unwrap());
+        writer
+            .add_directory("output/", SimpleFileOptions::default())
+            .unwrap();
+        for entry in fs::read_dir(Path::new(FIXTURES).join("output")).unwrap() {
+            let path = entry.unwrap().path();
+            let name = format!("output/{}", path.file_name().unwrap().to_string_lossy());
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(&fs::read(&path).unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+        zip_path
+    }
+
+    // Returns the spiders of the source, sorted, and the number of POIs built from it.
+    fn count_pois(source: &Source, spiders: &[String]) -> (Vec<String>, usize) {
+        let mut seen = vec![];
+        let mut pois = 0;
+        let mut reader = source.reader().unwrap();
+        for entry in source.entries(spiders).unwrap() {
+            seen.push(entry.spider.clone());
+            let extracted = reader
+                .read(&entry, |file| {
+                    extract_features(file, &CountryBoundariesGeocoder)
+                })
+                .unwrap()
+                .unwrap();
+            pois += extracted.pois.map_or(0, |value| value.len());
+        }
+        seen.sort();
+        (seen, pois)
+    }
+
+    #[test]
+    fn test_from_path() {
+        let dir = TempDir::new("source").expect("Failed to create temp dir");
+        assert_eq!(
+            Source::from_path(dir.path()),
+            Source::Directory(dir.path().to_path_buf())
+        );
+        let zip_path = dir.path().join("output.zip");
+        assert_eq!(Source::from_path(&zip_path), Source::Zip(zip_path.clone()));
+        assert_eq!(
+            Source::from_path(&zip_path).run_info_path(),
+            Some(dir.path().join("output.json"))
+        );
+    }
+
+    #[test]
+    fn test_extract_fixture_archive() {
+        let dir = TempDir::new("source").expect("Failed to create temp dir");
+        let zip_path = build_fixture_archive(dir.path());
+        let work_dir = dir.path().join("work");
+
+        let files_directory = Source::from_path(&zip_path).extract(&work_dir).unwrap();
+        assert_eq!(files_directory, work_dir.join("output"));
+        assert!(files_directory.join("spider_a.geojson").is_file());
+    }
+
+    #[test]
+    fn test_entries_directory() {
+        let (spiders, pois) = count_pois(&Source::from_path(Path::new(FIXTURES)), &[]);
+        assert_eq!(spiders, vec!["spider_a", "spider_b", "spider_empty"]);
+        assert_eq!(pois, 3);
+    }
+
+    #[test]
+    fn test_entries_zip() {
+        let dir = TempDir::new("source").expect("Failed to create temp dir");
+        let zip_path = build_fixture_archive(dir.path());
+
+        let (spiders, pois) = count_pois(&Source::from_path(&zip_path), &[]);
+        assert_eq!(spiders, vec!["spider_a", "spider_b", "spider_empty"]);
+        assert_eq!(pois, 3);
+        // Nothing was extracted next to the archive.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_entries_spider_filter() {
+        let dir = TempDir::new("source").expect("Failed to create temp dir");
+        let zip_path = build_fixture_archive(dir.path());
+
+        let (spiders, pois) =
+            count_pois(&Source::from_path(&zip_path), &[String::from("spider_b")]);
+        assert_eq!(spiders, vec!["spider_b"]);
+        assert_eq!(pois, 1);
+    }
+}
@@ -0,0 +1,63 @@
+use geo::Point;
+use lazy_static::lazy_static;
+use tzf_rs::DefaultFinder;
+
+lazy_static! {
+    static ref FINDER: DefaultFinder = DefaultFinder::new();
+}
+
+/// Finds the IANA time zone of a point in the time zone boundaries bundled with `tzf-rs`.
+///
+/// Points at sea get the nautical zone of their longitude, e.g. `Etc/GMT`, so only
+/// coordinates out of range have no time zone.
+pub fn find_timezone(point: &Point) -> Option<&'static str> {
+    let name = FINDER.get_tz_name(point.x(), point.y());
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_timezone() {
+        assert_eq!(
+            find_timezone(&Point::new(-74.0060152, 40.7127281)),
+            Some("America/New_York")
+        );
+        // States split between two zones.
+        assert_eq!(
+            find_timezone(&Point::new(-106.4, 31.8)),
+            Some("America/Denver")
+        );
+        assert_eq!(
+            find_timezone(&Point::new(-87.2, 30.42)),
+            Some("America/Chicago")
+        );
+        assert_eq!(
+            find_timezone(&Point::new(-122.4, 37.8)),
+            Some("America/Los_Angeles")
+        );
+        assert_eq!(
+            find_timezone(&Point::new(2.35, 48.85)),
+            Some("Europe/Paris")
+        );
+        assert_eq!(
+            find_timezone(&Point::new(-15.4, 28.1)),
+            Some("Atlantic/Canary")
+        );
+        assert_eq!(
+            find_timezone(&Point::new(82.9, 55.0)),
+            Some("Asia/Novosibirsk")
+        );
+    }
+
+    #[test]
+    fn test_find_timezone_at_sea() {
+        assert_eq!(
+            find_timezone(&Point::new(3.864293, 54.375721)),
+            Some("Etc/GMT")
+        );
+        assert_eq!(find_timezone(&Point::new(200.0, 100.0)), None);
+    }
+}
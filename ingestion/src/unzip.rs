@@ -1,15 +1,18 @@
 use std::fs::File;
+use std::path::Path;
 
 use log::info;
 
-pub fn unzip(file_path: String, output_directory: String) {
-    let file = File::open(&file_path).unwrap();
-    let mut archive = zip::ZipArchive::new(file).unwrap();
-    archive
-        .extract(&output_directory)
-        .unwrap_or_else(|_| panic!("couldn't extract {} to {}", file_path, output_directory));
+use crate::error::IngestionError;
+
+pub fn unzip(file_path: &Path, output_directory: &Path) -> Result<(), IngestionError> {
+    let file = File::open(file_path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    archive.extract(output_directory)?;
     info!(
         "{} successfully extracted to {}",
-        file_path, output_directory
+        file_path.display(),
+        output_directory.display()
     );
+    Ok(())
 }
@@ -1,8 +1,29 @@
 CREATE TABLE brand (
     id SERIAL PRIMARY KEY,
     name VARCHAR(255) NOT NULL,
+    normalized_name VARCHAR(255) NOT NULL,
     wikidata_id VARCHAR(255),
-    CONSTRAINT brand_name UNIQUE (name)
+    merged_into_id INTEGER,
+    CONSTRAINT brand_wikidata_id UNIQUE (wikidata_id)
 );
 
 CREATE INDEX idx_brand_id ON brand (id);
+-- Brands without a Wikidata id are identified by their name.
+CREATE UNIQUE INDEX idx_brand_normalized_name ON brand (normalized_name)
+    WHERE wikidata_id IS NULL AND merged_into_id IS NULL;
+
+CREATE TABLE brand_alias (
+    brand_id INTEGER NOT NULL,
+    name VARCHAR(255) NOT NULL,
+    normalized_name VARCHAR(255) NOT NULL,
+    CONSTRAINT brand_alias_pkey PRIMARY KEY (brand_id, name)
+);
+
+CREATE INDEX idx_brand_alias_normalized_name ON brand_alias (normalized_name);
+
+CREATE TABLE operator (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    wikidata_id VARCHAR(255),
+    CONSTRAINT operator_name UNIQUE (name)
+);
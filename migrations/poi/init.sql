@@ -1,11 +1,58 @@
+CREATE TABLE run (
+    id SERIAL PRIMARY KEY,
+    atp_run_at TIMESTAMPTZ,
+    source_url TEXT,
+    zip_size BIGINT,
+    rows_announced BIGINT,
+    spiders_announced INTEGER,
+    rows_ingested BIGINT NOT NULL DEFAULT 0,
+    files_ingested INTEGER NOT NULL DEFAULT 0,
+    files_skipped INTEGER NOT NULL DEFAULT 0,
+    files_failed INTEGER NOT NULL DEFAULT 0,
+    features_dropped BIGINT NOT NULL DEFAULT 0,
+    report JSONB,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    finished_at TIMESTAMPTZ,
+    status VARCHAR(15) NOT NULL DEFAULT 'running'
+);
+
+CREATE TABLE spider (
+    id VARCHAR(255) PRIMARY KEY,
+    source_file TEXT NOT NULL,
+    brand_ids INTEGER[] NOT NULL DEFAULT '{}',
+    feature_count INTEGER NOT NULL DEFAULT 0,
+    previous_feature_count INTEGER,
+    features_dropped INTEGER NOT NULL DEFAULT 0,
+    last_run_id INTEGER,
+    status VARCHAR(15) NOT NULL,
+    error TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE category (
+    id SERIAL PRIMARY KEY,
+    key VARCHAR(50) NOT NULL,
+    value VARCHAR(255) NOT NULL,
+    CONSTRAINT category_key_value UNIQUE (key, value)
+);
+
 CREATE TABLE poi (
     id SERIAL PRIMARY KEY,
     spider_id VARCHAR(255) NOT NULL,
     poi_name TEXT,
     brand_id INTEGER,
-    website VARCHAR(255),
+    website TEXT,
+    website_host VARCHAR(255),
+    website_source VARCHAR(15),
     opening_hours TEXT,
+    opening_schedule JSONB,
+    -- Minutes since Monday midnight, in the local time of the POI.
+    opening_week INT4MULTIRANGE,
+    opening_hours_error TEXT,
+    timezone VARCHAR(64),
     phone VARCHAR(255),
+    phones TEXT[] NOT NULL DEFAULT '{}',
+    invalid_phones TEXT[] NOT NULL DEFAULT '{}',
     point GEOMETRY(POINT, 4326),
     city VARCHAR(255),
     zipcode VARCHAR(255),
@@ -15,8 +62,67 @@ CREATE TABLE poi (
     state VARCHAR(255),
     full_address TEXT,
     street_name TEXT,
-    country_code VARCHAR(15)
+    -- The address fields filled from the other ones rather than scraped.
+    address_derived TEXT[] NOT NULL DEFAULT '{}',
+    postcode_valid BOOLEAN,
+    country_code VARCHAR(15),
+    -- `geocoded`, `address_country` when the country comes from addr:country, or `not_geocoded`.
+    geocode_status VARCHAR(15) NOT NULL DEFAULT 'geocoded',
+    -- `out_of_range`, `null_island`, `swapped`, `country_mismatch` or `duplicate`.
+    coordinate_flags TEXT[] NOT NULL DEFAULT '{}',
+    -- ISO 3166-2, from the reverse geocoding like country_code.
+    subdivision_code VARCHAR(15),
+    -- The areas of the local boundary files, by level, e.g. {"municipality": {"id": "75056", "name": "Paris"}}.
+    admin_areas JSONB,
+    poi_ref VARCHAR(255),
+    branch TEXT,
+    email VARCHAR(255),
+    contact_twitter VARCHAR(255),
+    contact_facebook VARCHAR(255),
+    image TEXT,
+    located_in TEXT,
+    located_in_wikidata_id VARCHAR(255),
+    nsi_id VARCHAR(255),
+    end_date VARCHAR(255),
+    operator TEXT,
+    operator_wikidata_id VARCHAR(255),
+    tags JSONB NOT NULL DEFAULT '{}',
+    operator_id INTEGER,
+    category_id INTEGER,
+    feature_id VARCHAR(255) NOT NULL,
+    run_id INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deleted_at TIMESTAMPTZ,
+    CONSTRAINT poi_spider_feature UNIQUE (spider_id, feature_id)
 );
 
+CREATE TABLE spider_brand (
+    spider_id VARCHAR(255) NOT NULL,
+    brand_id INTEGER NOT NULL,
+    poi_count INTEGER NOT NULL,
+    CONSTRAINT spider_brand_pkey PRIMARY KEY (spider_id, brand_id)
+);
+
+CREATE INDEX idx_spider_brand_brand_id ON spider_brand (brand_id);
+
+CREATE TABLE brand_operator (
+    brand_id INTEGER NOT NULL,
+    operator_id INTEGER NOT NULL,
+    poi_count INTEGER NOT NULL,
+    CONSTRAINT brand_operator_pkey PRIMARY KEY (brand_id, operator_id)
+);
+
+CREATE INDEX idx_brand_operator_operator_id ON brand_operator (operator_id);
+
 CREATE INDEX idx_poi_point ON poi USING GIST (point);
 CREATE INDEX idx_poi_brand_id ON poi (brand_id);
+CREATE INDEX idx_poi_run_id ON poi (run_id);
+CREATE INDEX idx_poi_tags ON poi USING GIN (tags);
+CREATE INDEX idx_poi_operator_id ON poi (operator_id);
+CREATE INDEX idx_poi_category_id ON poi (category_id);
+CREATE INDEX idx_poi_website_host ON poi (website_host);
+CREATE INDEX idx_poi_subdivision_code ON poi (subdivision_code);
+CREATE INDEX idx_poi_admin_areas ON poi USING GIN (admin_areas);
+CREATE INDEX idx_poi_coordinate_flags ON poi USING GIN (coordinate_flags);
+CREATE INDEX idx_poi_opening_week ON poi USING GIST (opening_week);